@@ -0,0 +1,20 @@
+//! A REPL for the expression language from the 29.6 exercise.
+//!
+//! Scripts can be piped in, with results written one per line:
+//!
+//! ```text
+//! $ printf 'let x = 6\nx*7\n' | cargo run --bin expr_repl
+//! x = 6
+//! 42
+//! ```
+
+use std::io::{self, IsTerminal};
+
+#[path = "../days/day_4_afternoon/session_29_error_handling/expression/mod.rs"]
+mod expression;
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    expression::repl::Repl::new().run(stdin.lock(), io::stdout().lock(), prompt)
+}
@@ -1,3 +1,4 @@
+pub mod numeric;
 mod session_12_1_destructuring;
 mod session_12_2_let_control_flow;
 mod session_12_3_exercise_expression_evalutation;
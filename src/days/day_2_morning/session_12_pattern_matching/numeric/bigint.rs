@@ -0,0 +1,296 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// An arbitrary-precision signed integer.
+///
+/// The magnitude is stored as little-endian base 2^32 digits without leading
+/// zeros, so that every value has a single representation and zero has no
+/// digits at all.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt {
+            negative: false,
+            magnitude: self.magnitude.clone(),
+        }
+    }
+
+    /// The quotient and remainder of the division, truncating towards zero
+    /// like the division of primitive integers. Panics if `divisor` is zero.
+    pub fn div_rem(&self, divisor: &BigInt) -> (BigInt, BigInt) {
+        assert!(!divisor.is_zero(), "division by zero");
+        let (quotient, remainder) = div_rem_magnitudes(&self.magnitude, &divisor.magnitude);
+        (
+            BigInt::from_parts(self.negative != divisor.negative, quotient),
+            BigInt::from_parts(self.negative, remainder),
+        )
+    }
+
+    /// The greatest common divisor, which is never negative. The gcd of zero
+    /// and zero is zero.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, remainder) = a.div_rem(&b);
+            a = std::mem::replace(&mut b, remainder);
+        }
+        a
+    }
+
+    /// The value as an `f64`, rounded if it can't be represented exactly.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &digit| acc * 4_294_967_296.0 + f64::from(digit));
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        BigInt::from_parts(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let digit = u64::from(a.get(i).copied().unwrap_or(0))
+            + u64::from(b.get(i).copied().unwrap_or(0))
+            + carry;
+        sum.push(digit as u32);
+        carry = digit >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = u64::from(b.get(i).copied().unwrap_or(0)) + borrow;
+        let (digit, underflow) = u64::from(digit).overflowing_sub(subtrahend);
+        difference.push(digit as u32);
+        borrow = u64::from(underflow);
+    }
+    debug_assert_eq!(borrow, 0, "subtrahend larger than minuend");
+    difference
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let digit = u64::from(x) * u64::from(y) + u64::from(product[i + j]) + carry;
+            product[i + j] = digit as u32;
+            carry = digit >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+/// Long division, one bit at a time.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare_magnitudes(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::with_capacity(b.len() + 1);
+    for bit in (0..a.len() * 32).rev() {
+        // remainder = remainder * 2 + the next bit of `a`
+        let mut carry = (a[bit / 32] >> (bit % 32)) & 1;
+        for digit in remainder.iter_mut() {
+            let shifted = (*digit << 1) | carry;
+            carry = *digit >> 31;
+            *digit = shifted;
+        }
+        if carry != 0 {
+            remainder.push(carry);
+        }
+        if compare_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, b);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quotient, remainder)
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(
+                self.negative,
+                add_magnitudes(&self.magnitude, &rhs.magnitude),
+            );
+        }
+        // The signs differ, so the magnitudes cancel out and the larger one
+        // decides the sign.
+        match compare_magnitudes(&self.magnitude, &rhs.magnitude) {
+            Ordering::Less => BigInt::from_parts(
+                rhs.negative,
+                sub_magnitudes(&rhs.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::from_parts(
+                self.negative,
+                sub_magnitudes(&self.magnitude, &rhs.magnitude),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != rhs.negative,
+            mul_magnitudes(&self.magnitude, &rhs.magnitude),
+        )
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return f.write_str("0");
+        }
+        // Peel off nine decimal digits at a time, least significant first.
+        const CHUNK: u32 = 1_000_000_000;
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_magnitudes(&magnitude, &[CHUNK]);
+            chunks.push(remainder.first().copied().unwrap_or(0));
+            magnitude = BigInt::from_parts(false, quotient).magnitude;
+        }
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{chunk:09}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(&big(7) + &big(-10), big(-3));
+        assert_eq!(&big(-7) - &big(-10), big(3));
+        assert_eq!(&big(6) * &big(-7), big(-42));
+        assert_eq!(&big(5) - &big(5), BigInt::zero());
+        assert!(!(&big(-5) + &big(5)).is_negative());
+        assert_eq!(big(-7).div_rem(&big(2)), (big(-3), big(-1)));
+        assert_eq!(big(7).div_rem(&big(-2)), (big(-3), big(1)));
+        assert_eq!(big(48).gcd(&big(-18)), big(6));
+        assert_eq!(big(0).gcd(&big(-5)), big(5));
+        assert!(big(-10) < big(-9) && big(-1) < big(0) && big(3) < big(1 << 40));
+    }
+
+    #[test]
+    fn growth() {
+        let max = big(i64::MAX);
+        let square = &max * &max;
+        assert_eq!(square.to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(square.div_rem(&max), (max.clone(), BigInt::zero()));
+        assert_eq!(&(&square + &big(5)) - &square, big(5));
+        assert_eq!(big(i64::MIN).to_string(), i64::MIN.to_string());
+        assert_eq!((-&big(i64::MIN)).to_string(), "9223372036854775808");
+        assert_eq!(big(1_000_000_000).to_string(), "1000000000");
+        assert_eq!(square.to_f64(), (i64::MAX as f64) * (i64::MAX as f64));
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use thiserror::Error;
+
+pub mod bigint;
+pub mod rational;
+
+pub use rational::Rational;
+
+/// Why an arithmetic operation failed.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("arithmetic overflow")]
+    Overflow,
+}
+
+/// A kind of number that expressions can be evaluated with.
+pub trait Number: Clone + PartialEq + fmt::Debug + fmt::Display {
+    /// The number for a literal in an expression.
+    fn from_i64(value: i64) -> Self;
+
+    fn try_add(&self, rhs: &Self) -> Result<Self, ArithmeticError>;
+    fn try_sub(&self, rhs: &Self) -> Result<Self, ArithmeticError>;
+    fn try_mul(&self, rhs: &Self) -> Result<Self, ArithmeticError>;
+    fn try_div(&self, rhs: &Self) -> Result<Self, ArithmeticError>;
+}
+
+/// Checked integer arithmetic, with division truncating towards zero.
+impl Number for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn try_add(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_add(*rhs).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_sub(*rhs).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn try_mul(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        self.checked_mul(*rhs).ok_or(ArithmeticError::Overflow)
+    }
+
+    fn try_div(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        if *rhs == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        // Only `i64::MIN / -1` overflows.
+        self.checked_div(*rhs).ok_or(ArithmeticError::Overflow)
+    }
+}
+
+/// Floating-point arithmetic. Rather than producing infinities or NaN,
+/// dividing by zero is an error and so is a result too large to represent.
+/// Literals beyond 2^53 are rounded.
+impl Number for f64 {
+    fn from_i64(value: i64) -> Self {
+        value as f64
+    }
+
+    fn try_add(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        finite(self + rhs)
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        finite(self - rhs)
+    }
+
+    fn try_mul(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        finite(self * rhs)
+    }
+
+    fn try_div(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        if *rhs == 0.0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        finite(self / rhs)
+    }
+}
+
+fn finite(value: f64) -> Result<f64, ArithmeticError> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(ArithmeticError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checked_integers() {
+        assert_eq!(7i64.try_div(&-2), Ok(-3));
+        assert_eq!(i64::MAX.try_add(&1), Err(ArithmeticError::Overflow));
+        assert_eq!(i64::MIN.try_div(&-1), Err(ArithmeticError::Overflow));
+        assert_eq!(1i64.try_div(&0), Err(ArithmeticError::DivisionByZero));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(7f64.try_div(&-2.0), Ok(-3.5));
+        assert_eq!(f64::MAX.try_mul(&2.0), Err(ArithmeticError::Overflow));
+        assert_eq!(1f64.try_div(&-0.0), Err(ArithmeticError::DivisionByZero));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use super::bigint::BigInt;
+use super::{ArithmeticError, Number};
+
+/// An exact fraction of arbitrary-precision integers.
+///
+/// The fraction is always in lowest terms with a positive denominator, so
+/// equal values compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numer: BigInt,
+    denom: BigInt,
+}
+
+impl Rational {
+    /// The fraction `numer / denom`, or `None` if `denom` is zero.
+    pub fn new(numer: impl Into<BigInt>, denom: impl Into<BigInt>) -> Option<Self> {
+        let (numer, denom) = (numer.into(), denom.into());
+        if denom.is_zero() {
+            return None;
+        }
+        let gcd = numer.gcd(&denom);
+        let (mut numer, _) = numer.div_rem(&gcd);
+        let (mut denom, _) = denom.div_rem(&gcd);
+        if denom.is_negative() {
+            numer = -&numer;
+            denom = -&denom;
+        }
+        Some(Rational { numer, denom })
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.numer
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.denom
+    }
+
+    /// The value as an `f64`, rounded if it can't be represented exactly.
+    pub fn to_f64(&self) -> f64 {
+        self.numer.to_f64() / self.denom.to_f64()
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Rational {
+            numer: BigInt::from(value),
+            denom: BigInt::from(1),
+        }
+    }
+}
+
+/// Exact arithmetic, which can't overflow.
+impl Number for Rational {
+    fn from_i64(value: i64) -> Self {
+        Rational::from(value)
+    }
+
+    fn try_add(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        let numer = &(&self.numer * &rhs.denom) + &(&rhs.numer * &self.denom);
+        Ok(Rational::new(numer, &self.denom * &rhs.denom).unwrap())
+    }
+
+    fn try_sub(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        let numer = &(&self.numer * &rhs.denom) - &(&rhs.numer * &self.denom);
+        Ok(Rational::new(numer, &self.denom * &rhs.denom).unwrap())
+    }
+
+    fn try_mul(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        Ok(Rational::new(&self.numer * &rhs.numer, &self.denom * &rhs.denom).unwrap())
+    }
+
+    fn try_div(&self, rhs: &Self) -> Result<Self, ArithmeticError> {
+        Rational::new(&self.numer * &rhs.denom, &self.denom * &rhs.numer)
+            .ok_or(ArithmeticError::DivisionByZero)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.denom == BigInt::from(1) {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ratio(numer: i64, denom: i64) -> Rational {
+        Rational::new(numer, denom).unwrap()
+    }
+
+    #[test]
+    fn normalized() {
+        assert_eq!(ratio(6, -4), ratio(-3, 2));
+        assert_eq!(ratio(6, -4).to_string(), "-3/2");
+        assert_eq!(ratio(0, -5).to_string(), "0");
+        assert_eq!(ratio(10, 5).to_string(), "2");
+        assert_eq!(ratio(1, 8).to_f64(), 0.125);
+        assert_eq!(Rational::new(1, 0), None);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (third, sixth) = (ratio(1, 3), ratio(1, 6));
+        assert_eq!(third.try_add(&sixth), Ok(ratio(1, 2)));
+        assert_eq!(sixth.try_sub(&third), Ok(ratio(-1, 6)));
+        assert_eq!(third.try_mul(&ratio(-3, 4)), Ok(ratio(-1, 4)));
+        assert_eq!(third.try_div(&sixth), Ok(ratio(2, 1)));
+        assert_eq!(
+            third.try_div(&ratio(0, 1)),
+            Err(ArithmeticError::DivisionByZero)
+        );
+
+        let max = Rational::from(i64::MAX);
+        let square = max.try_mul(&max).unwrap();
+        assert_eq!(square.to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(square.try_div(&max).unwrap().try_div(&max), Ok(ratio(1, 1)));
+    }
+}
@@ -1,20 +1,24 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use super::numeric::{ArithmeticError, Number, Rational};
+
 /// # 12.3 Exercise: Expression Evaluation
 ///
 /// Let’s write a simple recursive evaluator for arithmetic expressions.
 ///
 /// - The `Box` type here is a smart pointer. An expression can be “boxed” with
-///   `Box::new`` as seen in the tests. To evaluate a boxed expression, use the
-///   deref operator to “unbox” `it: eval(*boxed_expr)`.
+///   `Box::new`` as seen in the tests. A boxed expression derefs to the
+///   expression it holds, so it can be evaluated with `eval(&boxed_expr)`.
 ///
-/// - Some expressions cannot be evaluated and will return an error. The `Res` type
-///   represents either a successful value or an error with a message. This is very
-///   similar to the standard-library `Result`.
+/// - Some expressions cannot be evaluated and will return an error. The
+///   `EvalError` type says what went wrong and in which subexpression, and is
+///   returned in the standard-library `Result`.
 ///
 
 /// An operation to perform on two subexpressions.
-
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Operation {
     Add,
     Sub,
@@ -22,8 +26,30 @@ enum Operation {
     Div,
 }
 
+impl Operation {
+    /// How tightly the operator binds, for printing minimal parentheses.
+    fn precedence(self) -> u8 {
+        match self {
+            Operation::Add | Operation::Sub => 1,
+            Operation::Mul | Operation::Div => 2,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+        };
+        f.write_str(symbol)
+    }
+}
+
 /// An expression, in tree form.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Expression {
     /// An operation on two subexpressions.
     Op {
@@ -37,50 +63,90 @@ enum Expression {
 }
 use Expression::{Op, Value};
 
-/// The result of evaluating an expression.
-#[derive(Debug, PartialEq, Eq)]
-enum Res {
-    /// Evaluation was successful, with the given result.
-    Ok(i64),
-    /// Evaluation failed, with the given error message.
-    Err(String),
-}
-// Allow `Ok` and `Err` as shorthands for `Res::Ok` and `Res::Err`.
-use Res::{Err, Ok};
-
-fn eval(e: Expression) -> Res {
-    match e {
-        Op { op, left, right } => match (eval(*left), eval(*right)) {
-            (Ok(v1), Ok(v2)) => match op {
-                Operation::Add => Ok(v1 + v2),
-                Operation::Sub => Ok(v1 - v2),
-                Operation::Mul => Ok(v1 * v2),
-                Operation::Div => {
-                    if v2 != 0 {
-                        Ok(v1 + v2)
+/// Prints the expression in infix form, with only the parentheses needed to
+/// keep its structure, e.g. `10 - (2 + 3) * 4`.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value(v) => write!(f, "{v}"),
+            Op { op, left, right } => {
+                // Operators are left-associative, so an operand on the right
+                // also needs parentheses if it binds equally tightly.
+                let needs_parens = |operand: &Expression, right: bool| match operand {
+                    Op { op: inner, .. } => {
+                        inner.precedence() < op.precedence()
+                            || (right && inner.precedence() == op.precedence())
+                    }
+                    Value(_) => false,
+                };
+                for (operand, right) in [(left, false), (right, true)] {
+                    if right {
+                        write!(f, " {op} ")?;
+                    }
+                    if needs_parens(operand, right) {
+                        write!(f, "({operand})")?;
                     } else {
-                        Err("division by zero".to_string())
+                        write!(f, "{operand}")?;
                     }
                 }
-            },
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Why an expression couldn't be evaluated, with the subexpression that
+/// failed.
+#[derive(Debug, Error, PartialEq, Eq)]
+enum EvalError {
+    #[error("division by zero in `{0}`")]
+    DivisionByZero(Expression),
+    #[error("arithmetic overflow in `{0}`")]
+    Overflow(Expression),
+}
 
-            (Err(msg), _) => Err(msg),
-            (_, Err(msg)) => Err(msg),
-        },
+/// Evaluate `e` with the arithmetic of `N`, which the caller picks, e.g.
+/// `eval::<i64>(&e)` for checked integers or `eval::<Rational>(&e)` for exact
+/// fractions.
+fn eval<N: Number>(e: &Expression) -> Result<N, EvalError> {
+    match e {
+        Op { op, left, right } => {
+            let (v1, v2) = (eval::<N>(left)?, eval::<N>(right)?);
+            let result = match op {
+                Operation::Add => v1.try_add(&v2),
+                Operation::Sub => v1.try_sub(&v2),
+                Operation::Mul => v1.try_mul(&v2),
+                Operation::Div => v1.try_div(&v2),
+            };
+            result.map_err(|error| match error {
+                ArithmeticError::DivisionByZero => EvalError::DivisionByZero(e.clone()),
+                ArithmeticError::Overflow => EvalError::Overflow(e.clone()),
+            })
+        }
+
+        Value(v) => Ok(N::from_i64(*v)),
+    }
+}
 
-        Value(v) => Ok(v),
+/// An operation on two operands, to keep the tests short.
+#[cfg(test)]
+fn op(op: Operation, left: Expression, right: Expression) -> Expression {
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
     }
 }
 
 #[test]
 fn test_value() {
-    assert_eq!(eval(Expression::Value(19)), Ok(19));
+    assert_eq!(eval::<i64>(&Expression::Value(19)), Ok(19));
 }
 
 #[test]
 fn test_sum() {
     assert_eq!(
-        eval(Expression::Op {
+        eval::<i64>(&Expression::Op {
             op: Operation::Add,
             left: Box::new(Expression::Value(10)),
             right: Box::new(Expression::Value(20)),
@@ -106,7 +172,7 @@ fn test_recursion() {
         right: Box::new(Expression::Value(5)),
     };
     assert_eq!(
-        eval(Expression::Op {
+        eval::<i64>(&Expression::Op {
             op: Operation::Add,
             left: Box::new(term1),
             right: Box::new(term2),
@@ -116,24 +182,136 @@ fn test_recursion() {
 }
 
 #[test]
-fn test_error() {
+fn test_division() {
     assert_eq!(
-        eval(Expression::Op {
+        eval::<i64>(&Expression::Op {
             op: Operation::Div,
-            left: Box::new(Expression::Value(99)),
-            right: Box::new(Expression::Value(0)),
+            left: Box::new(Expression::Value(-7)),
+            right: Box::new(Expression::Value(2)),
         }),
-        Err(String::from("division by zero"))
+        Ok(-3)
     );
 }
 
+#[test]
+fn test_error() {
+    let division = Expression::Op {
+        op: Operation::Div,
+        left: Box::new(Expression::Value(99)),
+        right: Box::new(Expression::Value(0)),
+    };
+    let expr = Expression::Op {
+        op: Operation::Add,
+        left: Box::new(Expression::Value(1)),
+        right: Box::new(division.clone()),
+    };
+    let error = eval::<i64>(&expr).unwrap_err();
+    assert_eq!(error, EvalError::DivisionByZero(division));
+    assert_eq!(error.to_string(), "division by zero in `99 / 0`");
+}
+
+#[test]
+fn test_overflow() {
+    let product = op(Operation::Mul, Value(i64::MAX), Value(2));
+    assert_eq!(
+        eval::<i64>(&op(Operation::Sub, Value(1), product.clone())),
+        Err(EvalError::Overflow(product))
+    );
+    let quotient = op(Operation::Div, Value(i64::MIN), Value(-1));
+    assert_eq!(
+        eval::<i64>(&quotient).unwrap_err().to_string(),
+        format!("arithmetic overflow in `{} / -1`", i64::MIN)
+    );
+    assert_eq!(
+        eval::<i64>(&op(Operation::Sub, Value(i64::MIN), Value(1))),
+        Err(EvalError::Overflow(op(
+            Operation::Sub,
+            Value(i64::MIN),
+            Value(1)
+        )))
+    );
+}
+
+#[test]
+fn test_display() {
+    let sum = op(Operation::Add, Value(2), Value(3));
+    let expr = op(
+        Operation::Sub,
+        Value(10),
+        op(Operation::Mul, sum.clone(), Value(4)),
+    );
+    assert_eq!(expr.to_string(), "10 - (2 + 3) * 4");
+    let expr = op(Operation::Sub, op(Operation::Sub, Value(1), Value(2)), sum);
+    assert_eq!(expr.to_string(), "1 - 2 - (2 + 3)");
+    let expr = op(
+        Operation::Div,
+        Value(8),
+        op(Operation::Div, Value(4), Value(-2)),
+    );
+    assert_eq!(expr.to_string(), "8 / (4 / -2)");
+}
+
+#[test]
+fn test_modes() {
+    // 1/3 + 1/6 - 1/2
+    let expr = op(
+        Operation::Sub,
+        op(
+            Operation::Add,
+            op(Operation::Div, Value(1), Value(3)),
+            op(Operation::Div, Value(1), Value(6)),
+        ),
+        op(Operation::Div, Value(1), Value(2)),
+    );
+    assert_eq!(eval::<i64>(&expr), Ok(0));
+    assert_eq!(eval::<Rational>(&expr), Ok(Rational::from(0)));
+    assert!(eval::<f64>(&expr).unwrap().abs() < 1e-15);
+
+    let third = op(Operation::Div, Value(10), Value(3));
+    assert_eq!(eval::<i64>(&third), Ok(3));
+    assert_eq!(eval::<Rational>(&third).unwrap().to_string(), "10/3");
+
+    // Exact results grow beyond 64 bits instead of overflowing.
+    let square = op(Operation::Mul, Value(i64::MAX), Value(i64::MAX));
+    assert_eq!(
+        eval::<i64>(&square),
+        Err(EvalError::Overflow(square.clone()))
+    );
+    let exact = op(Operation::Div, square, Value(i64::MAX));
+    assert_eq!(eval::<Rational>(&exact), Ok(Rational::from(i64::MAX)));
+
+    let division = op(
+        Operation::Div,
+        Value(1),
+        op(Operation::Sub, Value(2), Value(2)),
+    );
+    for error in [
+        eval::<i64>(&division).unwrap_err(),
+        eval::<Rational>(&division).unwrap_err(),
+        eval::<f64>(&division).unwrap_err(),
+    ] {
+        assert_eq!(error, EvalError::DivisionByZero(division.clone()));
+    }
+}
+
 #[allow(dead_code)]
 pub fn main() {
-    let result = eval(Expression::Op {
+    let result = eval::<i64>(&Expression::Op {
         op: Operation::Add,
         left: Box::new(Expression::Value(10)),
         right: Box::new(Expression::Value(20)),
     });
 
     assert_eq!(result, Ok(30));
+
+    let third = Expression::Op {
+        op: Operation::Div,
+        left: Box::new(Expression::Value(10)),
+        right: Box::new(Expression::Value(3)),
+    };
+    println!("{third} = {:?} as i64", eval::<i64>(&third));
+    println!("{third} = {:?} as f64", eval::<f64>(&third));
+    if let Ok(exact) = eval::<Rational>(&third) {
+        println!("{third} = {exact} exactly");
+    }
 }
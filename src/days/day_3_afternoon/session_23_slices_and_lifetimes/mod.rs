@@ -1,3 +1,4 @@
+pub mod protobuf;
 mod session_23_1_slices;
 mod session_23_2_string_references;
 mod session_23_3_lifetime_annotations;
@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use super::parser::{parse_message, Error, FieldValue, UnknownFields, WireType};
+use super::schema::{Cardinality, FieldType, MessageDescriptor, ScalarType, Schema};
+use super::writer::{encode_varint, zigzag_encode, MessageWriter};
+
+/// A value decoded using a schema. Strings and bytes borrow from the input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    Double(f64),
+    Float(f32),
+    Int32(i32),
+    Int64(i64),
+    Uint32(u32),
+    Uint64(u64),
+    Bool(bool),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Message(DynamicMessage<'a>),
+    /// The values of a repeated field, in the order they were encountered.
+    Repeated(Vec<Value<'a>>),
+}
+
+/// A field of a `DynamicMessage`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicField<'a> {
+    pub number: u64,
+    pub name: String,
+    pub value: Value<'a>,
+}
+
+/// A message decoded using a `MessageDescriptor` rather than Rust code.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DynamicMessage<'a> {
+    /// The fields present in the message, ordered by field number.
+    pub fields: Vec<DynamicField<'a>>,
+    /// Fields not in the descriptor, kept for `encode`.
+    pub unknown_fields: UnknownFields<'a>,
+}
+
+impl<'a> DynamicMessage<'a> {
+    /// Look up a field by its name.
+    pub fn get(&self, name: &str) -> Option<&Value<'a>> {
+        self.fields
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| &f.value)
+    }
+}
+
+/// The default limit on the nesting depth of messages, as in the reference
+/// implementation.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// Decode a message of type `descriptor`, whose message-typed fields are
+/// looked up in `schema`.
+///
+/// Fields not in the descriptor are kept as unknown fields. If a singular
+/// field occurs more than once, the last value wins. Repeated numeric fields
+/// are accepted both packed and unpacked.
+pub fn decode<'a>(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    data: &'a [u8],
+) -> Result<DynamicMessage<'a>, Error> {
+    decode_with_max_depth(schema, descriptor, data, DEFAULT_MAX_DEPTH)
+}
+
+/// Like `decode`, but failing with `Error::RecursionLimit` if messages are
+/// nested more than `max_depth` levels deep. The outermost message is at
+/// depth 1.
+pub fn decode_with_max_depth<'a>(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    data: &'a [u8],
+    max_depth: usize,
+) -> Result<DynamicMessage<'a>, Error> {
+    if max_depth == 0 {
+        return Err(Error::RecursionLimit);
+    }
+    let mut fields: BTreeMap<u64, DynamicField<'a>> = BTreeMap::new();
+    let mut unknown_fields = UnknownFields::default();
+    parse_message(data, |field| {
+        let Some(field_descriptor) = descriptor.field(field.field_num) else {
+            unknown_fields.push(field);
+            return Ok(());
+        };
+        let entry = fields
+            .entry(field.field_num)
+            .or_insert_with(|| DynamicField {
+                number: field.field_num,
+                name: field_descriptor.name.clone(),
+                value: Value::Repeated(Vec::new()),
+            });
+        let field_type = &field_descriptor.field_type;
+        match (field_descriptor.cardinality, &mut entry.value) {
+            (Cardinality::Repeated, Value::Repeated(values)) => match (field_type, &field.value) {
+                (FieldType::Scalar(scalar), FieldValue::Len(_))
+                    if scalar.wire_type() != WireType::Len =>
+                {
+                    decode_packed(*scalar, &field.value, values)?
+                }
+                _ => values.push(decode_value(schema, field_type, &field.value, max_depth)?),
+            },
+            _ => entry.value = decode_value(schema, field_type, &field.value, max_depth)?,
+        }
+        Ok(())
+    })?;
+    Ok(DynamicMessage {
+        fields: fields.into_values().collect(),
+        unknown_fields,
+    })
+}
+
+/// Encode `message` as a message of type `descriptor`, whose message-typed
+/// fields are looked up in `schema`.
+///
+/// Fields are written in field number order, with repeated numeric fields
+/// packed and empty repeated fields left out, followed by the unknown fields
+/// exactly as they were decoded.
+pub fn encode<W: Write>(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    message: &DynamicMessage,
+    writer: &mut MessageWriter<W>,
+) -> Result<(), Error> {
+    for field in &message.fields {
+        let Some(field_descriptor) = descriptor.field(field.number) else {
+            return Err(Error::TypeMismatch);
+        };
+        let field_type = &field_descriptor.field_type;
+        match (field_descriptor.cardinality, field_type, &field.value) {
+            (Cardinality::Repeated, _, Value::Repeated(values)) if values.is_empty() => {}
+            (Cardinality::Repeated, FieldType::Scalar(scalar), Value::Repeated(values))
+                if scalar.wire_type() != WireType::Len =>
+            {
+                writer.write_bytes(field.number, &encode_packed(*scalar, values)?)?;
+            }
+            (Cardinality::Repeated, _, Value::Repeated(values)) => {
+                for value in values {
+                    encode_value(schema, field.number, field_type, value, writer)?;
+                }
+            }
+            (Cardinality::Repeated, _, _) => return Err(Error::TypeMismatch),
+            (_, _, value) => encode_value(schema, field.number, field_type, value, writer)?,
+        }
+    }
+    writer.write_unknown_fields(&message.unknown_fields)
+}
+
+fn encode_value<W: Write>(
+    schema: &Schema,
+    field_num: u64,
+    field_type: &FieldType,
+    value: &Value,
+    writer: &mut MessageWriter<W>,
+) -> Result<(), Error> {
+    match (field_type, value) {
+        (FieldType::Scalar(scalar), value) => {
+            writer.write_value(field_num, &encode_scalar(*scalar, value)?)
+        }
+        (FieldType::Message(name), Value::Message(message)) => {
+            let descriptor = message_descriptor(schema, name)?;
+            writer.write_message(field_num, |nested| {
+                encode(schema, descriptor, message, nested)
+            })
+        }
+        _ => Err(Error::TypeMismatch),
+    }
+}
+
+/// Encode the payload of a packed repeated field of numeric `values`.
+pub fn encode_packed(scalar: ScalarType, values: &[Value]) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    for value in values {
+        match encode_scalar(scalar, value)? {
+            FieldValue::Varint(value) => encode_varint(value, &mut data),
+            FieldValue::I64(value) => data.extend_from_slice(&value.to_le_bytes()),
+            FieldValue::I32(value) => data.extend_from_slice(&value.to_le_bytes()),
+            FieldValue::Len(_) => return Err(Error::TypeMismatch),
+        }
+    }
+    Ok(data)
+}
+
+/// Convert a value to its wire representation. This is the inverse of
+/// `decode_scalar`.
+pub fn encode_scalar<'a>(scalar: ScalarType, value: &Value<'a>) -> Result<FieldValue<'a>, Error> {
+    Ok(match (scalar, value) {
+        (ScalarType::Double, Value::Double(v)) => FieldValue::I64(v.to_bits() as i64),
+        (ScalarType::Float, Value::Float(v)) => FieldValue::I32(v.to_bits() as i32),
+        (ScalarType::Int32, Value::Int32(v)) => FieldValue::Varint(*v as i64 as u64),
+        (ScalarType::Int64, Value::Int64(v)) => FieldValue::Varint(*v as u64),
+        (ScalarType::Uint32, Value::Uint32(v)) => FieldValue::Varint(*v as u64),
+        (ScalarType::Uint64, Value::Uint64(v)) => FieldValue::Varint(*v),
+        (ScalarType::Sint32, Value::Int32(v)) => FieldValue::Varint(zigzag_encode(*v as i64)),
+        (ScalarType::Sint64, Value::Int64(v)) => FieldValue::Varint(zigzag_encode(*v)),
+        (ScalarType::Fixed32, Value::Uint32(v)) => FieldValue::I32(*v as i32),
+        (ScalarType::Fixed64, Value::Uint64(v)) => FieldValue::I64(*v as i64),
+        (ScalarType::Sfixed32, Value::Int32(v)) => FieldValue::I32(*v),
+        (ScalarType::Sfixed64, Value::Int64(v)) => FieldValue::I64(*v),
+        (ScalarType::Bool, Value::Bool(v)) => FieldValue::Varint(*v as u64),
+        (ScalarType::String, Value::String(v)) => FieldValue::Len(v.as_bytes()),
+        (ScalarType::Bytes, Value::Bytes(v)) => FieldValue::Len(v),
+        _ => return Err(Error::TypeMismatch),
+    })
+}
+
+fn decode_value<'a>(
+    schema: &Schema,
+    field_type: &FieldType,
+    value: &FieldValue<'a>,
+    max_depth: usize,
+) -> Result<Value<'a>, Error> {
+    match field_type {
+        FieldType::Scalar(scalar) => decode_scalar(*scalar, value),
+        FieldType::Message(name) => {
+            let descriptor = message_descriptor(schema, name)?;
+            let data = value.as_bytes()?;
+            Ok(Value::Message(decode_with_max_depth(
+                schema,
+                descriptor,
+                data,
+                max_depth - 1,
+            )?))
+        }
+    }
+}
+
+/// Look up the message type `name` in `schema`.
+///
+/// `Schema::parse` resolves every message reference, but `descriptor` may
+/// come from a different schema than the one passed along with it.
+pub fn message_descriptor<'s>(
+    schema: &'s Schema,
+    name: &str,
+) -> Result<&'s MessageDescriptor, Error> {
+    schema
+        .message(name)
+        .ok_or_else(|| Error::UnknownMessageType(name.to_string()))
+}
+
+/// Decode the elements of a packed repeated field, appending them to `values`.
+fn decode_packed<'a>(
+    scalar: ScalarType,
+    value: &FieldValue<'a>,
+    values: &mut Vec<Value<'a>>,
+) -> Result<(), Error> {
+    match scalar.wire_type() {
+        WireType::Varint => {
+            for element in value.as_packed_varints()? {
+                values.push(decode_scalar(scalar, &FieldValue::Varint(element?))?);
+            }
+        }
+        WireType::I32 => {
+            for element in value.as_packed_fixed32()? {
+                values.push(decode_scalar(scalar, &FieldValue::I32(element? as i32))?);
+            }
+        }
+        WireType::I64 => {
+            for element in value.as_packed_fixed64()? {
+                values.push(decode_scalar(scalar, &FieldValue::I64(element? as i64))?);
+            }
+        }
+        WireType::Len => values.push(decode_scalar(scalar, value)?),
+    }
+    Ok(())
+}
+
+fn decode_scalar<'a>(scalar: ScalarType, value: &FieldValue<'a>) -> Result<Value<'a>, Error> {
+    Ok(match scalar {
+        ScalarType::Double => Value::Double(value.as_f64()?),
+        ScalarType::Float => Value::Float(value.as_f32()?),
+        ScalarType::Int32 => Value::Int32(value.as_i64()? as i32),
+        ScalarType::Int64 => Value::Int64(value.as_i64()?),
+        ScalarType::Uint32 => Value::Uint32(value.as_u64()? as u32),
+        ScalarType::Uint64 => Value::Uint64(value.as_u64()?),
+        ScalarType::Sint32 => Value::Int32(value.as_sint32()?),
+        ScalarType::Sint64 => Value::Int64(value.as_sint64()?),
+        ScalarType::Fixed32 => Value::Uint32(value.as_fixed32()?),
+        ScalarType::Fixed64 => Value::Uint64(value.as_fixed64()?),
+        ScalarType::Sfixed32 => Value::Int32(value.as_fixed32()? as i32),
+        ScalarType::Sfixed64 => Value::Int64(value.as_fixed64()? as i64),
+        ScalarType::Bool => Value::Bool(value.as_u64()? != 0),
+        ScalarType::String => Value::String(value.as_string()?),
+        ScalarType::Bytes => Value::Bytes(value.as_bytes()?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PERSON_PROTO: &str = "
+        message PhoneNumber {
+            optional string number = 1;
+            optional string type = 2;
+        }
+
+        message Person {
+            optional string name = 1;
+            optional int32 id = 2;
+            repeated PhoneNumber phones = 3;
+        }
+    ";
+
+    #[test]
+    fn person() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer
+            .write_message(3, |phone| phone.write_string(1, "+1202-555-1212"))
+            .unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(99, 1).unwrap(); // unknown field
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1800-867-5308")?;
+                phone.write_string(2, "mobile")
+            })
+            .unwrap();
+        let data = writer.into_inner();
+
+        let person = decode(&schema, schema.message("Person").unwrap(), &data).unwrap();
+        let numbers: Vec<u64> = person.fields.iter().map(|f| f.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert_eq!(person.get("name"), Some(&Value::String("maxwell")));
+        assert_eq!(person.get("id"), Some(&Value::Int32(42)));
+
+        let Some(Value::Repeated(phones)) = person.get("phones") else {
+            panic!("phones should be repeated");
+        };
+        assert_eq!(phones.len(), 2);
+        let Value::Message(phone) = &phones[1] else {
+            panic!("phone should be a message");
+        };
+        assert_eq!(phone.get("number"), Some(&Value::String("+1800-867-5308")));
+        assert_eq!(phone.get("type"), Some(&Value::String("mobile")));
+    }
+
+    #[test]
+    fn scalars() {
+        let schema = Schema::parse(
+            "message Scalars {
+                sint32 s32 = 1;
+                sint64 s64 = 2;
+                int64 i64 = 3;
+                double d = 4;
+                float f = 5;
+                bool b = 6;
+                sfixed32 sf32 = 7;
+            }",
+        )
+        .unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_varint(1, 3).unwrap();
+        writer.write_varint(2, 4).unwrap();
+        writer.write_int64(3, -5).unwrap();
+        writer.write_double(4, 0.25).unwrap();
+        writer.write_i32(5, 1.5f32.to_bits() as i32).unwrap();
+        writer.write_varint(6, 1).unwrap();
+        writer.write_i32(7, -6).unwrap();
+        let data = writer.into_inner();
+
+        let message = decode(&schema, schema.message("Scalars").unwrap(), &data).unwrap();
+        assert_eq!(message.get("s32"), Some(&Value::Int32(-2)));
+        assert_eq!(message.get("s64"), Some(&Value::Int64(2)));
+        assert_eq!(message.get("i64"), Some(&Value::Int64(-5)));
+        assert_eq!(message.get("d"), Some(&Value::Double(0.25)));
+        assert_eq!(message.get("f"), Some(&Value::Float(1.5)));
+        assert_eq!(message.get("b"), Some(&Value::Bool(true)));
+        assert_eq!(message.get("sf32"), Some(&Value::Int32(-6)));
+    }
+
+    #[test]
+    fn packed() {
+        let schema = Schema::parse(
+            "message Packed {
+                repeated int32 ints = 1;
+                repeated sint64 sints = 2;
+                repeated double doubles = 3;
+                repeated fixed32 fixeds = 4;
+            }",
+        )
+        .unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_packed_varints(1, [1, 2]).unwrap();
+        // Packed and unpacked encodings may be mixed for the same field.
+        writer.write_varint(1, 3).unwrap();
+        writer
+            .write_packed_varints(2, [zigzag_encode(-1), zigzag_encode(5)])
+            .unwrap();
+        writer
+            .write_packed_fixed64(3, [0.5f64.to_bits(), 2.0f64.to_bits()])
+            .unwrap();
+        writer.write_packed_fixed32(4, [7, 8]).unwrap();
+        let data = writer.into_inner();
+
+        let message = decode(&schema, schema.message("Packed").unwrap(), &data).unwrap();
+        assert_eq!(
+            message.get("ints"),
+            Some(&Value::Repeated(vec![
+                Value::Int32(1),
+                Value::Int32(2),
+                Value::Int32(3)
+            ]))
+        );
+        assert_eq!(
+            message.get("sints"),
+            Some(&Value::Repeated(vec![Value::Int64(-1), Value::Int64(5)]))
+        );
+        assert_eq!(
+            message.get("doubles"),
+            Some(&Value::Repeated(vec![
+                Value::Double(0.5),
+                Value::Double(2.0)
+            ]))
+        );
+        assert_eq!(
+            message.get("fixeds"),
+            Some(&Value::Repeated(vec![Value::Uint32(7), Value::Uint32(8)]))
+        );
+
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_bytes(3, &[0; 9]).unwrap();
+        let data = writer.into_inner();
+        let error = decode(&schema, schema.message("Packed").unwrap(), &data).unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedEOF));
+    }
+
+    #[test]
+    fn unknown_round_trip() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let person = schema.message("Person").unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1202-555-1212")?;
+                phone.write_varint(7, 1)
+            })
+            .unwrap();
+        writer.write_double(4, 1.25).unwrap();
+        writer.write_string(5, "from a newer schema").unwrap();
+        let data = writer.into_inner();
+
+        let message = decode(&schema, person, &data).unwrap();
+        assert_eq!(message.unknown_fields.len(), 2);
+
+        let mut writer = MessageWriter::new(Vec::new());
+        encode(&schema, person, &message, &mut writer).unwrap();
+        assert_eq!(writer.into_inner(), data);
+    }
+
+    #[test]
+    fn encode_packed() {
+        let schema = Schema::parse(
+            "message Packed {
+                repeated sint32 sints = 1;
+                repeated float floats = 2;
+                repeated string names = 3;
+            }",
+        )
+        .unwrap();
+        let descriptor = schema.message("Packed").unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer
+            .write_packed_varints(1, [zigzag_encode(-3), zigzag_encode(3)])
+            .unwrap();
+        writer
+            .write_packed_fixed32(2, [0.5f32.to_bits(), 4.0f32.to_bits()])
+            .unwrap();
+        writer.write_string(3, "a").unwrap();
+        writer.write_string(3, "b").unwrap();
+        let data = writer.into_inner();
+
+        let message = decode(&schema, descriptor, &data).unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        encode(&schema, descriptor, &message, &mut writer).unwrap();
+        assert_eq!(writer.into_inner(), data);
+
+        let empty = DynamicMessage {
+            fields: vec![
+                DynamicField {
+                    number: 1,
+                    name: "sints".to_string(),
+                    value: Value::Repeated(Vec::new()),
+                },
+                DynamicField {
+                    number: 3,
+                    name: "names".to_string(),
+                    value: Value::Repeated(Vec::new()),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut writer = MessageWriter::new(Vec::new());
+        encode(&schema, descriptor, &empty, &mut writer).unwrap();
+        assert_eq!(writer.into_inner(), []);
+
+        let mismatched = DynamicMessage {
+            fields: vec![DynamicField {
+                number: 1,
+                name: "sints".to_string(),
+                value: Value::Uint32(1),
+            }],
+            ..Default::default()
+        };
+        let mut writer = MessageWriter::new(Vec::new());
+        assert!(matches!(
+            encode(&schema, descriptor, &mismatched, &mut writer),
+            Err(Error::TypeMismatch)
+        ));
+    }
+
+    #[test]
+    fn max_depth() {
+        let schema = Schema::parse("message Node { Node child = 1; int32 value = 2; }").unwrap();
+        let node = schema.message("Node").unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer
+            .write_message(1, |child| {
+                child.write_message(1, |grandchild| grandchild.write_varint(2, 3))
+            })
+            .unwrap();
+        let data = writer.into_inner();
+
+        assert!(decode_with_max_depth(&schema, node, &data, 3).is_ok());
+        let error = decode_with_max_depth(&schema, node, &data, 2).unwrap_err();
+        assert!(matches!(error.kind(), Error::RecursionLimit));
+        assert_eq!(error.path().unwrap().to_string(), "1[0].1");
+    }
+
+    #[test]
+    fn foreign_descriptor() {
+        // A descriptor from another schema, whose message types `schema`
+        // doesn't have.
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let other = Schema::parse("message Box { Item item = 1; } message Item {}").unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_message(1, |_| Ok(())).unwrap();
+        let data = writer.into_inner();
+        let error = decode(&schema, other.message("Box").unwrap(), &data).unwrap_err();
+        assert!(matches!(error.kind(), Error::UnknownMessageType(name) if name == "Item"));
+
+        let message = DynamicMessage {
+            fields: vec![DynamicField {
+                number: 1,
+                name: "item".to_string(),
+                value: Value::Message(DynamicMessage::default()),
+            }],
+            ..Default::default()
+        };
+        let mut writer = MessageWriter::new(Vec::new());
+        assert!(matches!(
+            encode(&schema, other.message("Box").unwrap(), &message, &mut writer),
+            Err(Error::UnknownMessageType(name)) if name == "Item"
+        ));
+    }
+
+    #[test]
+    fn wrong_wire_type() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer
+            .write_message(3, |phone| phone.write_varint(2, 7))
+            .unwrap();
+        let data = writer.into_inner();
+        let error = decode(&schema, schema.message("Person").unwrap(), &data).unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedWireType));
+        assert_eq!(error.offset(), Some(11));
+        assert_eq!(error.path().unwrap().to_string(), "3[0].2");
+    }
+}
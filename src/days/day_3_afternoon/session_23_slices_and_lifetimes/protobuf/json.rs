@@ -0,0 +1,787 @@
+use std::fmt::Write as _;
+use thiserror::Error;
+
+use super::dynamic::{
+    decode, encode_packed, encode_scalar, message_descriptor, DynamicMessage, Value,
+};
+use super::parser::{Error, WireType};
+use super::schema::{
+    Cardinality, FieldDescriptor, FieldType, MessageDescriptor, ScalarType, Schema,
+};
+use super::writer::MessageWriter;
+
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error("Protobuf error: {0}")]
+    Protobuf(#[from] Error),
+    #[error("Invalid JSON at offset {0}: {1}")]
+    Syntax(usize, &'static str),
+    #[error("Unknown field '{1}' in message {0}")]
+    UnknownField(String, String),
+    #[error("Invalid value for field {0}")]
+    InvalidValue(String),
+}
+
+/// Transcode a message of type `descriptor` to JSON, following the canonical
+/// proto3 JSON mapping.
+///
+/// Field names are converted to lowerCamelCase, 64-bit integers are written
+/// as strings, bytes as base64, and repeated fields as arrays. Empty repeated
+/// fields and unlabelled fields holding their default value are left out.
+/// Unknown fields cannot be represented in JSON, so they are dropped.
+pub fn to_json(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    data: &[u8],
+) -> Result<String, JsonError> {
+    let message = decode(schema, descriptor, data)?;
+    let mut out = String::new();
+    write_message(schema, descriptor, &message, &mut out)?;
+    Ok(out)
+}
+
+/// Transcode JSON to a message of type `descriptor`. This is the inverse of
+/// `to_json`.
+///
+/// Fields may be named in lowerCamelCase or as in the `.proto` file, and
+/// `null` values are skipped. Integers are accepted as numbers or strings.
+pub fn from_json(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    json: &str,
+) -> Result<Vec<u8>, JsonError> {
+    let json = JsonParser {
+        input: json.as_bytes(),
+        pos: 0,
+    }
+    .parse_document()?;
+    let mut writer = MessageWriter::new(Vec::new());
+    write_json_message(schema, descriptor, &json, &mut writer)?;
+    Ok(writer.into_inner())
+}
+
+/// Convert a field name to lowerCamelCase, as `protoc` does.
+pub fn json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut capitalize = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            json_name.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+fn write_message(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    message: &DynamicMessage,
+    out: &mut String,
+) -> Result<(), JsonError> {
+    out.push('{');
+    let mut first = true;
+    for field in &message.fields {
+        // `decode` only produces fields of the descriptor.
+        let field_descriptor = descriptor.field(field.number).ok_or(Error::TypeMismatch)?;
+        if is_omitted(field_descriptor.cardinality, &field.value) {
+            continue;
+        }
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        write_string(&json_name(&field.name), out);
+        out.push(':');
+        write_value(schema, &field_descriptor.field_type, &field.value, out)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// Whether a field is left out of canonical JSON: an empty repeated field,
+/// or a field without a label, which has no presence in proto3, holding the
+/// default value of its type.
+fn is_omitted(cardinality: Cardinality, value: &Value) -> bool {
+    match (cardinality, value) {
+        (_, Value::Repeated(values)) => values.is_empty(),
+        (Cardinality::Singular, Value::Double(v)) => v.to_bits() == 0,
+        (Cardinality::Singular, Value::Float(v)) => v.to_bits() == 0,
+        (Cardinality::Singular, Value::Int32(v)) => *v == 0,
+        (Cardinality::Singular, Value::Int64(v)) => *v == 0,
+        (Cardinality::Singular, Value::Uint32(v)) => *v == 0,
+        (Cardinality::Singular, Value::Uint64(v)) => *v == 0,
+        (Cardinality::Singular, Value::Bool(v)) => !v,
+        (Cardinality::Singular, Value::String(v)) => v.is_empty(),
+        (Cardinality::Singular, Value::Bytes(v)) => v.is_empty(),
+        _ => false,
+    }
+}
+
+fn write_value(
+    schema: &Schema,
+    field_type: &FieldType,
+    value: &Value,
+    out: &mut String,
+) -> Result<(), JsonError> {
+    match value {
+        Value::Double(v) => write_float(*v, &v.to_string(), out),
+        Value::Float(v) => write_float(*v as f64, &v.to_string(), out),
+        Value::Int32(v) => write!(out, "{v}").unwrap(),
+        Value::Uint32(v) => write!(out, "{v}").unwrap(),
+        Value::Int64(v) => write!(out, "\"{v}\"").unwrap(),
+        Value::Uint64(v) => write!(out, "\"{v}\"").unwrap(),
+        Value::Bool(v) => write!(out, "{v}").unwrap(),
+        Value::String(v) => write_string(v, out),
+        Value::Bytes(v) => write!(out, "\"{}\"", base64_encode(v)).unwrap(),
+        Value::Message(message) => {
+            let FieldType::Message(name) = field_type else {
+                return Err(Error::TypeMismatch.into());
+            };
+            write_message(schema, message_descriptor(schema, name)?, message, out)?;
+        }
+        Value::Repeated(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(schema, field_type, value, out)?;
+            }
+            out.push(']');
+        }
+    }
+    Ok(())
+}
+
+/// Write a float, where `text` is its shortest representation. JSON has no
+/// representation for non-finite numbers, so they are written as strings.
+fn write_float(value: f64, text: &str, out: &mut String) {
+    if value.is_nan() {
+        out.push_str("\"NaN\"");
+    } else if value == f64::INFINITY {
+        out.push_str("\"Infinity\"");
+    } else if value == f64::NEG_INFINITY {
+        out.push_str("\"-Infinity\"");
+    } else {
+        out.push_str(text);
+    }
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_json_message(
+    schema: &Schema,
+    descriptor: &MessageDescriptor,
+    json: &Json,
+    writer: &mut MessageWriter<Vec<u8>>,
+) -> Result<(), JsonError> {
+    let Json::Object(members) = json else {
+        return Err(JsonError::InvalidValue(descriptor.name.clone()));
+    };
+    for (key, value) in members {
+        let Some(field) = descriptor
+            .fields
+            .iter()
+            .find(|f| json_name(&f.name) == *key || f.name == *key)
+        else {
+            return Err(JsonError::UnknownField(
+                descriptor.name.clone(),
+                key.clone(),
+            ));
+        };
+        match (field.cardinality, value) {
+            (_, Json::Null) => {}
+            (Cardinality::Repeated, Json::Array(elements)) => match field.field_type {
+                // Proto3 writes nothing at all for an empty repeated field.
+                _ if elements.is_empty() => {}
+                FieldType::Scalar(scalar) if scalar.wire_type() != WireType::Len => {
+                    let values = elements
+                        .iter()
+                        .map(|element| json_scalar(field, scalar, element))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    writer.write_bytes(field.number, &encode_packed(scalar, &values)?)?;
+                }
+                _ => {
+                    for element in elements {
+                        write_json_field(schema, field, element, writer)?;
+                    }
+                }
+            },
+            (Cardinality::Repeated, _) => {
+                return Err(JsonError::InvalidValue(field.name.clone()));
+            }
+            (_, value) => write_json_field(schema, field, value, writer)?,
+        }
+    }
+    Ok(())
+}
+
+/// Write a single (non-repeated) value of `field`.
+fn write_json_field(
+    schema: &Schema,
+    field: &FieldDescriptor,
+    json: &Json,
+    writer: &mut MessageWriter<Vec<u8>>,
+) -> Result<(), JsonError> {
+    match &field.field_type {
+        FieldType::Message(name) => {
+            let descriptor = message_descriptor(schema, name)?;
+            let mut nested = MessageWriter::new(Vec::new());
+            write_json_message(schema, descriptor, json, &mut nested)?;
+            writer.write_bytes(field.number, &nested.into_inner())?;
+        }
+        FieldType::Scalar(ScalarType::Bytes) => {
+            let Json::String(text) = json else {
+                return Err(JsonError::InvalidValue(field.name.clone()));
+            };
+            let data =
+                base64_decode(text).ok_or_else(|| JsonError::InvalidValue(field.name.clone()))?;
+            writer.write_bytes(field.number, &data)?;
+        }
+        FieldType::Scalar(scalar) => {
+            let value = json_scalar(field, *scalar, json)?;
+            writer.write_value(field.number, &encode_scalar(*scalar, &value)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert a JSON value to a scalar of type `scalar`, other than bytes.
+fn json_scalar<'j>(
+    field: &FieldDescriptor,
+    scalar: ScalarType,
+    json: &'j Json,
+) -> Result<Value<'j>, JsonError> {
+    let invalid = || JsonError::InvalidValue(field.name.clone());
+    let value = match (scalar, json) {
+        (ScalarType::Bool, Json::Bool(v)) => Value::Bool(*v),
+        (ScalarType::String, Json::String(v)) => Value::String(v),
+        (ScalarType::Double, Json::Number(text) | Json::String(text)) => {
+            Value::Double(parse_float(text).ok_or_else(invalid)?)
+        }
+        (ScalarType::Float, Json::Number(text) | Json::String(text)) => {
+            let v = parse_float(text).ok_or_else(invalid)?;
+            if v.is_finite() && (v as f32).is_infinite() {
+                return Err(invalid());
+            }
+            Value::Float(v as f32)
+        }
+        (
+            ScalarType::Int32 | ScalarType::Sint32 | ScalarType::Sfixed32,
+            Json::Number(text) | Json::String(text),
+        ) => {
+            let v = parse_integer(text).ok_or_else(invalid)?;
+            Value::Int32(i32::try_from(v).map_err(|_| invalid())?)
+        }
+        (
+            ScalarType::Int64 | ScalarType::Sint64 | ScalarType::Sfixed64,
+            Json::Number(text) | Json::String(text),
+        ) => {
+            let v = parse_integer(text).ok_or_else(invalid)?;
+            Value::Int64(i64::try_from(v).map_err(|_| invalid())?)
+        }
+        (ScalarType::Uint32 | ScalarType::Fixed32, Json::Number(text) | Json::String(text)) => {
+            let v = parse_integer(text).ok_or_else(invalid)?;
+            Value::Uint32(u32::try_from(v).map_err(|_| invalid())?)
+        }
+        (ScalarType::Uint64 | ScalarType::Fixed64, Json::Number(text) | Json::String(text)) => {
+            let v = parse_integer(text).ok_or_else(invalid)?;
+            Value::Uint64(u64::try_from(v).map_err(|_| invalid())?)
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+fn parse_float(text: &str) -> Option<f64> {
+    match text {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        // Rust also accepts spellings such as "inf", which JSON does not.
+        _ if text.ends_with(|c: char| c.is_ascii_digit()) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parse an integer, which may also be written with a fraction or exponent
+/// as long as its value is integral.
+fn parse_integer(text: &str) -> Option<i128> {
+    if let Ok(v) = text.parse() {
+        return Some(v);
+    }
+    let v: f64 = parse_float(text)?;
+    (v.is_finite() && v.fract() == 0.0 && v.abs() < 2f64.powi(64)).then_some(v as i128)
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data` as standard base64, with padding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bytes = [0u8; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard or URL-safe base64, with or without padding.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut n_bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        n_bits += 6;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            out.push((bits >> n_bits) as u8);
+            bits &= (1 << n_bits) - 1;
+        }
+    }
+    // A single leftover character cannot encode a whole byte.
+    (n_bits < 6).then_some(out)
+}
+
+/// A parsed JSON value. Numbers are kept as text, so that 64-bit integers
+/// are not rounded.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// The deepest nesting of JSON arrays and objects accepted.
+const MAX_JSON_DEPTH: usize = 100;
+
+struct JsonParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError::Syntax(self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8, message: &'static str) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.input.get(self.pos) != Some(&c) {
+            return Err(self.error(message));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_document(mut self) -> Result<Json, JsonError> {
+        let value = self.parse_value(0)?;
+        self.skip_whitespace();
+        if self.pos != self.input.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Json, JsonError> {
+        if depth > MAX_JSON_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.skip_whitespace();
+        let Some(&c) = self.input.get(self.pos) else {
+            return Err(self.error("unexpected end of input"));
+        };
+        match c {
+            b'{' => {
+                self.pos += 1;
+                let mut members: Vec<(String, Json)> = Vec::new();
+                self.skip_whitespace();
+                if self.input.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.expect(b'"', "expected a string")?;
+                    let key = self.parse_string()?;
+                    if members.iter().any(|(k, _)| *k == key) {
+                        return Err(self.error("duplicate key"));
+                    }
+                    self.expect(b':', "expected ':'")?;
+                    let value = self.parse_value(depth + 1)?;
+                    members.push((key, value));
+                    self.skip_whitespace();
+                    match self.input.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut elements = Vec::new();
+                self.skip_whitespace();
+                if self.input.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(elements));
+                }
+                loop {
+                    elements.push(self.parse_value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.input.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(elements));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            b'"' => {
+                self.pos += 1;
+                Ok(Json::String(self.parse_string()?))
+            }
+            b'-' | b'0'..=b'9' => self.parse_number(),
+            _ => {
+                for (literal, value) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.input[self.pos..].starts_with(literal.as_bytes()) {
+                        self.pos += literal.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+        }
+    }
+
+    /// Parse the rest of a string, after the opening quote.
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        let mut value = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&c) = self.input.get(self.pos) {
+                if c == b'"' || c == b'\\' || c < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The input came from a `&str` and we only stopped at ASCII
+            // characters, so this is a whole number of UTF-8 characters.
+            value.push_str(std::str::from_utf8(&self.input[start..self.pos]).unwrap());
+            match self.input.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let Some(&escape) = self.input.get(self.pos) else {
+                        return Err(self.error("unexpected end of input"));
+                    };
+                    self.pos += 1;
+                    value.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// Parse the rest of a `\u` escape, including a following low surrogate.
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.parse_hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.parse_hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while let Some(b'0'..=b'9') = parser.input.get(parser.pos) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+        if self.input.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        if self.input.get(self.pos) == Some(&b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return Err(self.error("invalid number"));
+        }
+        if self.input.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.input.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.input.get(self.pos) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        // Numbers are ASCII, so this is valid UTF-8.
+        let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap();
+        Ok(Json::Number(text.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PERSON_PROTO: &str = "
+        message PhoneNumber {
+            optional string number = 1;
+            optional string phone_type = 2;
+        }
+
+        message Person {
+            optional string name = 1;
+            optional int32 id = 2;
+            repeated PhoneNumber phones = 3;
+            optional int64 created_at = 4;
+            optional bytes photo = 5;
+            repeated sint32 lucky_numbers = 6;
+            optional double score = 7;
+            optional bool active = 8;
+        }
+    ";
+
+    #[test]
+    fn person() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let person = schema.message("Person").unwrap();
+        let json = r#"{"name":"max \"the\" well","id":42,"phones":[{"number":"+1202-555-1212","phoneType":"home"},{"number":"+1800-867-5308"}],"createdAt":"-1700000000000","photo":"AAEC/w==","luckyNumbers":[7,-3],"score":0.5,"active":true}"#;
+
+        let data = from_json(&schema, person, json).unwrap();
+        assert_eq!(to_json(&schema, person, &data).unwrap(), json);
+    }
+
+    #[test]
+    fn defaults() {
+        let schema = Schema::parse(
+            "message Defaults {
+                int32 count = 1;
+                string label = 2;
+                bool flag = 3;
+                double ratio = 4;
+                bytes data = 5;
+                repeated int64 ids = 6;
+                repeated string tags = 7;
+                optional int32 explicit = 8;
+                Defaults child = 9;
+            }",
+        )
+        .unwrap();
+        let descriptor = schema.message("Defaults").unwrap();
+
+        // Empty repeated fields aren't written at all.
+        let data = from_json(&schema, descriptor, r#"{"ids": [], "tags": []}"#).unwrap();
+        assert_eq!(data, []);
+
+        // Unlabelled fields holding their default are left out of the JSON,
+        // unlike fields with presence.
+        let json = r#"{"count":0,"label":"","flag":false,"ratio":0,"data":"","explicit":0,"child":{"count":0}}"#;
+        let data = from_json(&schema, descriptor, json).unwrap();
+        assert_eq!(
+            to_json(&schema, descriptor, &data).unwrap(),
+            r#"{"explicit":0,"child":{}}"#
+        );
+        let json = r#"{"count":-1,"label":"x","flag":true,"ratio":-0,"ids":["0"]}"#;
+        let data = from_json(&schema, descriptor, json).unwrap();
+        assert_eq!(to_json(&schema, descriptor, &data).unwrap(), json);
+    }
+
+    #[test]
+    fn lenient_input() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let person = schema.message("Person").unwrap();
+        let json = r#"
+            {
+                "name": "é😀",
+                "id": "42",
+                "created_at": 1e3,
+                "photo": "AAEC_w",
+                "phones": [],
+                "score": "NaN",
+                "active": null
+            }
+        "#;
+        let data = from_json(&schema, person, json).unwrap();
+        assert_eq!(
+            to_json(&schema, person, &data).unwrap(),
+            r#"{"name":"é😀","id":42,"createdAt":"1000","photo":"AAEC/w==","score":"NaN"}"#
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let person = schema.message("Person").unwrap();
+        assert!(matches!(
+            from_json(&schema, person, r#"{"nickname": "max"}"#),
+            Err(JsonError::UnknownField(message, field)) if message == "Person" && field == "nickname"
+        ));
+        assert!(matches!(
+            from_json(&schema, person, r#"{"id": 3000000000}"#),
+            Err(JsonError::InvalidValue(field)) if field == "id"
+        ));
+        assert!(matches!(
+            from_json(&schema, person, r#"{"id": 1.5}"#),
+            Err(JsonError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            from_json(&schema, person, r#"{"phones": {}}"#),
+            Err(JsonError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            from_json(&schema, person, r#"{"name": "max",}"#),
+            Err(JsonError::Syntax(15, _))
+        ));
+        assert!(matches!(
+            from_json(&schema, person, r#"{"name": "max"} x"#),
+            Err(JsonError::Syntax(16, "trailing characters"))
+        ));
+        assert!(matches!(
+            from_json(&schema, person, &"[".repeat(1000)),
+            Err(JsonError::Syntax(_, "nested too deeply"))
+        ));
+    }
+
+    #[test]
+    fn foreign_descriptor() {
+        let schema = Schema::parse(PERSON_PROTO).unwrap();
+        let other = Schema::parse("message Box { Item item = 1; } message Item {}").unwrap();
+        let descriptor = other.message("Box").unwrap();
+        assert!(matches!(
+            from_json(&schema, descriptor, r#"{"item": {}}"#),
+            Err(JsonError::Protobuf(Error::UnknownMessageType(name))) if name == "Item"
+        ));
+        assert!(matches!(
+            to_json(&schema, descriptor, &[0x0a, 0x00]),
+            Err(JsonError::Protobuf(error))
+                if matches!(error.kind(), Error::UnknownMessageType(name) if name == "Item")
+        ));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(json_name("phone_type"), "phoneType");
+        assert_eq!(json_name("a_b_c"), "aBC");
+        assert_eq!(json_name("name"), "name");
+    }
+
+    #[test]
+    fn base64() {
+        for (data, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (&[0xfb, 0xff], "+/8="),
+        ] {
+            assert_eq!(base64_encode(data), text);
+            assert_eq!(base64_decode(text).unwrap(), data);
+        }
+        assert_eq!(base64_decode("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(base64_decode("Z"), None);
+        assert_eq!(base64_decode("Zm9v!"), None);
+    }
+}
@@ -0,0 +1,8 @@
+pub mod dynamic;
+pub mod json;
+pub mod parser;
+pub mod raw;
+pub mod schema;
+pub mod stream;
+pub mod view;
+pub mod writer;
@@ -0,0 +1,752 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid varint")]
+    InvalidVarint,
+    #[error("Invalid wire-type")]
+    InvalidWireType,
+    #[error("Unexpected EOF")]
+    UnexpectedEOF,
+    #[error("Invalid length")]
+    InvalidSize(#[from] std::num::TryFromIntError),
+    #[error("Unexpected wire-type)")]
+    UnexpectedWireType,
+    #[error("Invalid string (not UTF-8)")]
+    InvalidString,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Value does not match the field type")]
+    TypeMismatch,
+    #[error("Message is {0} bytes, larger than the limit of {1} bytes")]
+    MessageTooLarge(u64, usize),
+    #[error("Messages are nested too deeply")]
+    RecursionLimit,
+    #[error("Unknown message type '{0}'")]
+    UnknownMessageType(String),
+    /// An error located in the input of `parse_field` or `parse_message`.
+    #[error("{source} at offset {offset} (field {path})")]
+    At {
+        /// Offset into the buffer passed to the outermost `parse_message`.
+        offset: usize,
+        path: FieldPath,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The underlying error, without its location.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::At { source, .. } => source.kind(),
+            error => error,
+        }
+    }
+
+    /// The offset at which the error occurred, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Error::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The path of the field in which the error occurred, if known.
+    pub fn path(&self) -> Option<&FieldPath> {
+        match self {
+            Error::At { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Attach a location to an error that does not have one yet.
+    fn at(self, offset: usize, path: FieldPath) -> Error {
+        match self {
+            Error::At { .. } => self,
+            error => Error::At {
+                offset,
+                path,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Locate an error returned while handling the field `segment`, which
+    /// starts at `field_offset` and whose payload starts at `payload_offset`.
+    ///
+    /// Errors that are already located come from parsing the payload as a
+    /// nested message, so their offset is relative to the payload.
+    pub(super) fn within(
+        self,
+        segment: PathSegment,
+        field_offset: usize,
+        payload_offset: usize,
+    ) -> Error {
+        match self {
+            Error::At {
+                offset,
+                mut path,
+                source,
+            } => {
+                path.0.insert(0, segment);
+                Error::At {
+                    offset: payload_offset + offset,
+                    path,
+                    source,
+                }
+            }
+            error => error.at(field_offset, FieldPath(vec![segment])),
+        }
+    }
+
+    /// Move a located error `by` bytes further into the input.
+    pub(super) fn shifted(self, by: usize) -> Error {
+        match self {
+            Error::At {
+                offset,
+                path,
+                source,
+            } => Error::At {
+                offset: offset + by,
+                path,
+                source,
+            },
+            error => error,
+        }
+    }
+}
+
+/// One step of a `FieldPath`: the `index`-th occurrence of a field within
+/// its message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathSegment {
+    pub field_num: u64,
+    pub index: usize,
+}
+
+/// The field numbers leading from the root message to a field, displayed
+/// like `3[1].1` for field 1 of the second field 3.
+///
+/// The occurrence index is always shown for enclosing messages, but only
+/// shown for the innermost field if it is not the first occurrence.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FieldPath(pub Vec<PathSegment>);
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>");
+        }
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", segment.field_num)?;
+            if i + 1 < self.0.len() || segment.index > 0 {
+                write!(f, "[{}]", segment.index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A wire type as seen on the wire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireType {
+    /// The Varint WireType indicates the value is a single VARINT.
+    Varint,
+    /// The I64 WireType indicates that the value is precisely 8 bytes in little-endian order
+    /// containing a 64-bit signed integer or double.
+    I64,
+    /// The Len WireType indicates that the value is a length represented as a VARINT
+    /// followed by exactly that number of bytes.
+    Len,
+    /// The I32 WireType indicates that the value is precisely 4 bytes in little-endian order
+    /// containing a 32-bit signed integer.
+    I32,
+}
+
+#[derive(Debug, PartialEq)]
+/// A field's value, typed based on the wire type.
+pub enum FieldValue<'a> {
+    Varint(u64),
+    I64(i64),
+    Len(&'a [u8]),
+    I32(i32),
+}
+
+#[derive(Debug, PartialEq)]
+/// A field, containing the field number and its value.
+pub struct Field<'a> {
+    pub field_num: u64,
+    pub value: FieldValue<'a>,
+    /// The complete encoding of the field, including its tag.
+    pub raw: &'a [u8],
+}
+
+/// A field that was not recognized while parsing a message. It is kept as
+/// the raw bytes of the field, so that it can be written back unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnknownField<'a> {
+    pub field_num: u64,
+    pub wire_type: WireType,
+    /// The complete encoding of the field, including its tag.
+    pub raw: &'a [u8],
+}
+
+/// The unknown fields of a message, in the order they were encountered.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UnknownFields<'a>(Vec<UnknownField<'a>>);
+
+impl<'a> UnknownFields<'a> {
+    /// Keep `field`, typically from the fallback arm of a field callback.
+    pub fn push(&mut self, field: Field<'a>) {
+        self.0.push(UnknownField {
+            field_num: field.field_num,
+            wire_type: field.value.wire_type(),
+            raw: field.raw,
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &UnknownField<'a>> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<u64> for WireType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<WireType, Error> {
+        Ok(match value {
+            0 => WireType::Varint,
+            1 => WireType::I64,
+            2 => WireType::Len,
+            5 => WireType::I32,
+            _ => return Err(Error::InvalidWireType),
+        })
+    }
+}
+
+impl<'a> FieldValue<'a> {
+    /// The wire type this value is encoded with.
+    pub fn wire_type(&self) -> WireType {
+        match self {
+            FieldValue::Varint(_) => WireType::Varint,
+            FieldValue::I64(_) => WireType::I64,
+            FieldValue::Len(_) => WireType::Len,
+            FieldValue::I32(_) => WireType::I32,
+        }
+    }
+
+    pub fn as_string(&self) -> Result<&'a str, Error> {
+        let FieldValue::Len(data) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        std::str::from_utf8(data).map_err(|_| Error::InvalidString)
+    }
+
+    pub fn as_bytes(&self) -> Result<&'a [u8], Error> {
+        let FieldValue::Len(data) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(data)
+    }
+
+    pub fn as_u64(&self) -> Result<u64, Error> {
+        let FieldValue::Varint(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(*value)
+    }
+
+    /// Interpret a VARINT as an `int64`, where negative values are stored
+    /// in two's complement.
+    pub fn as_i64(&self) -> Result<i64, Error> {
+        let FieldValue::Varint(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(*value as i64)
+    }
+
+    pub fn as_fixed64(&self) -> Result<u64, Error> {
+        let FieldValue::I64(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(*value as u64)
+    }
+
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        let FieldValue::I64(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(f64::from_bits(*value as u64))
+    }
+
+    pub fn as_fixed32(&self) -> Result<u32, Error> {
+        let FieldValue::I32(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(*value as u32)
+    }
+
+    pub fn as_f32(&self) -> Result<f32, Error> {
+        let FieldValue::I32(value) = self else {
+            return Err(Error::UnexpectedWireType);
+        };
+        Ok(f32::from_bits(*value as u32))
+    }
+
+    /// Interpret a VARINT as a ZigZag-encoded `sint64`.
+    pub fn as_sint64(&self) -> Result<i64, Error> {
+        Ok(zigzag_decode(self.as_u64()?))
+    }
+
+    /// Interpret a VARINT as a ZigZag-encoded `sint32`.
+    pub fn as_sint32(&self) -> Result<i32, Error> {
+        Ok(zigzag_decode(self.as_u64()? as u32 as u64) as i32)
+    }
+
+    /// Interpret a Len value as a packed sequence of VARINTs.
+    pub fn as_packed_varints(&self) -> Result<PackedVarints<'a>, Error> {
+        Ok(PackedVarints(self.as_bytes()?))
+    }
+
+    /// Interpret a Len value as a packed sequence of 4-byte values.
+    pub fn as_packed_fixed32(&self) -> Result<PackedFixed32<'a>, Error> {
+        Ok(PackedFixed32(self.as_bytes()?))
+    }
+
+    /// Interpret a Len value as a packed sequence of 8-byte values.
+    pub fn as_packed_fixed64(&self) -> Result<PackedFixed64<'a>, Error> {
+        Ok(PackedFixed64(self.as_bytes()?))
+    }
+}
+
+/// Decode a ZigZag-encoded signed integer, as used by `sint32` and `sint64`.
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// An iterator over the VARINTs of a packed repeated field, borrowing the
+/// payload.
+///
+/// After yielding an error, the iterator is exhausted.
+pub struct PackedVarints<'a>(&'a [u8]);
+
+impl<'a> Iterator for PackedVarints<'a> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(match parse_varint(self.0) {
+            Ok((value, remainder)) => {
+                self.0 = remainder;
+                Ok(value)
+            }
+            Err(e) => {
+                self.0 = &[];
+                Err(e)
+            }
+        })
+    }
+}
+
+/// An iterator over the little-endian 4-byte values of a packed repeated
+/// `fixed32`, `sfixed32` or `float` field, borrowing the payload.
+///
+/// A trailing partial value is reported as `Error::UnexpectedEOF`.
+pub struct PackedFixed32<'a>(&'a [u8]);
+
+impl<'a> Iterator for PackedFixed32<'a> {
+    type Item = Result<u32, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        if self.0.len() < 4 {
+            self.0 = &[];
+            return Some(Err(Error::UnexpectedEOF));
+        }
+        let (value, remainder) = self.0.split_at(4);
+        self.0 = remainder;
+        // Unwrap error because `value` is definitely 4 bytes long.
+        Some(Ok(u32::from_le_bytes(value.try_into().unwrap())))
+    }
+}
+
+/// An iterator over the little-endian 8-byte values of a packed repeated
+/// `fixed64`, `sfixed64` or `double` field, borrowing the payload.
+///
+/// A trailing partial value is reported as `Error::UnexpectedEOF`.
+pub struct PackedFixed64<'a>(&'a [u8]);
+
+impl<'a> Iterator for PackedFixed64<'a> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        if self.0.len() < 8 {
+            self.0 = &[];
+            return Some(Err(Error::UnexpectedEOF));
+        }
+        let (value, remainder) = self.0.split_at(8);
+        self.0 = remainder;
+        // Unwrap error because `value` is definitely 8 bytes long.
+        Some(Ok(u64::from_le_bytes(value.try_into().unwrap())))
+    }
+}
+
+/// Parse a VARINT, returning the parsed value and the remaining bytes.
+///
+/// A VARINT is at most 10 bytes long. The 10th byte only contributes the
+/// most significant bit of the `u64`, so any larger value there overflows.
+pub fn parse_varint(data: &[u8]) -> Result<(u64, &[u8]), Error> {
+    for i in 0..10 {
+        let Some(b) = data.get(i) else {
+            return Err(Error::InvalidVarint);
+        };
+        if i == 9 && *b > 1 {
+            // Either more bits than fit in a u64, or a continuation bit.
+            return Err(Error::InvalidVarint);
+        }
+        if b & 0x80 == 0 {
+            // This is the last byte of the VARINT, so convert it to
+            // a u64 and return it.
+            let mut value = 0u64;
+            for b in data[..=i].iter().rev() {
+                value = (value << 7) | (b & 0x7f) as u64;
+            }
+            return Ok((value, &data[i + 1..]));
+        }
+    }
+
+    // More than 10 bytes is invalid.
+    Err(Error::InvalidVarint)
+}
+
+/// Convert a tag into a field number and a WireType.
+pub fn unpack_tag(tag: u64) -> Result<(u64, WireType), Error> {
+    let field_num = tag >> 3;
+    let wire_type = WireType::try_from(tag & 0x7)?;
+    Ok((field_num, wire_type))
+}
+
+/// Parse a field, returning the remaining bytes
+///
+/// Errors are located relative to the start of `data`.
+pub fn parse_field(data: &[u8]) -> Result<(Field<'_>, &[u8]), Error> {
+    // 1. Read and unpack the tag.
+    let (tag, remainder) = parse_varint(data).map_err(|e| e.at(0, FieldPath::default()))?;
+    let path = FieldPath(vec![PathSegment {
+        field_num: tag >> 3,
+        index: 0,
+    }]);
+    let (field_num, wire_type) = unpack_tag(tag).map_err(|e| e.at(0, path.clone()))?;
+    // Errors in the value are located at its start.
+    let value_offset = data.len() - remainder.len();
+    let located = |e: Error| e.at(value_offset, path.clone());
+
+    // 2. Based on the wire type, build a Field, consuming as many bytes as
+    //    necessary.
+    let (fieldvalue, remainder) = match wire_type {
+        WireType::Varint => {
+            let (value, remainder) = parse_varint(remainder).map_err(located)?;
+            (FieldValue::Varint(value), remainder)
+        }
+
+        WireType::I64 => {
+            if remainder.len() < 8usize {
+                return Err(located(Error::UnexpectedEOF));
+            }
+            let (value, remainder) = remainder.split_at(8);
+            // Unwrap error because `value` is definitely 8 bytes long.
+            let value = i64::from_le_bytes(value.try_into().unwrap());
+            (FieldValue::I64(value), remainder)
+        }
+
+        WireType::Len => {
+            let (len, remainder) = parse_varint(remainder).map_err(located)?;
+            let len: usize = len.try_into().map_err(|e| located(Error::from(e)))?;
+            if remainder.len() < len {
+                return Err(located(Error::UnexpectedEOF));
+            }
+            let (value, remainder) = remainder.split_at(len);
+            (FieldValue::Len(value), remainder)
+        }
+
+        WireType::I32 => {
+            if remainder.len() < 4usize {
+                return Err(located(Error::UnexpectedEOF));
+            }
+            let (value, remainder) = remainder.split_at(4);
+            // Unwrap error because `value` is definitely 4 bytes long.
+            let value = i32::from_le_bytes(value.try_into().unwrap());
+            (FieldValue::I32(value), remainder)
+        }
+    };
+
+    // 3. Return the field, and any un-consumed bytes.
+    Ok((
+        Field {
+            field_num,
+            value: fieldvalue,
+            raw: &data[..data.len() - remainder.len()],
+        },
+        remainder,
+    ))
+}
+
+/// Parse a message in the given data, calling `field_callback` for each field in the message.
+///
+/// The entire input is consumed.
+///
+/// Errors, including those returned by `field_callback`, are located by their
+/// offset into `data` and the path of the field they occurred in. If the
+/// callback parses a nested message from `field.value.as_bytes()`, its errors
+/// are located relative to the outermost message.
+pub fn parse_message<'a>(
+    mut data: &'a [u8],
+    mut field_callback: impl FnMut(Field<'a>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut offset = 0;
+    let mut occurrences: HashMap<u64, usize> = HashMap::new();
+    while !data.is_empty() {
+        let (field, remainder) = parse_field(data).map_err(|e| match e {
+            Error::At {
+                offset: field_offset,
+                mut path,
+                source,
+            } => {
+                for segment in &mut path.0 {
+                    segment.index = occurrences.get(&segment.field_num).copied().unwrap_or(0);
+                }
+                Error::At {
+                    offset: offset + field_offset,
+                    path,
+                    source,
+                }
+            }
+            e => e,
+        })?;
+        let field_len = data.len() - remainder.len();
+        let index = occurrences.entry(field.field_num).or_default();
+        let segment = PathSegment {
+            field_num: field.field_num,
+            index: *index,
+        };
+        *index += 1;
+        // A Len payload is always the last part of the field.
+        let payload_offset = match field.value {
+            FieldValue::Len(payload) => offset + field_len - payload.len(),
+            _ => offset,
+        };
+        field_callback(field).map_err(|e| e.within(segment, offset, payload_offset))?;
+        offset += field_len;
+        data = remainder;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint_ten_bytes() {
+        let data = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x42,
+        ];
+        let (value, remainder) = parse_varint(&data).unwrap();
+        assert_eq!(value, u64::MAX);
+        assert_eq!(remainder, &[0x42]);
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let data = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        assert!(matches!(parse_varint(&data), Err(Error::InvalidVarint)));
+        let data = [0x80; 11];
+        assert!(matches!(parse_varint(&data), Err(Error::InvalidVarint)));
+    }
+
+    #[test]
+    fn negative_int64() {
+        // Field 2, varint, value -2.
+        let data = [
+            0x10, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        let (field, remainder) = parse_field(&data).unwrap();
+        assert_eq!(field.field_num, 2);
+        assert_eq!(field.value.as_i64().unwrap(), -2);
+        assert!(remainder.is_empty());
+    }
+
+    #[test]
+    fn i64_field() {
+        // Field 1, I64, value 1.5.
+        let mut data = vec![0x09];
+        data.extend_from_slice(&1.5f64.to_le_bytes());
+        let (field, _) = parse_field(&data).unwrap();
+        assert_eq!(field.field_num, 1);
+        assert_eq!(field.value.as_f64().unwrap(), 1.5);
+        assert_eq!(field.value.as_fixed64().unwrap(), 1.5f64.to_bits());
+        assert!(matches!(
+            field.value.as_u64(),
+            Err(Error::UnexpectedWireType)
+        ));
+
+        let error = parse_field(&data[..5]).unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedEOF));
+        assert_eq!(error.offset(), Some(1));
+    }
+
+    #[test]
+    fn error_location() {
+        // A Person whose second phone number has a truncated `number`.
+        let phone = [0x0a, 0x02, 0x2b, 0x31];
+        let bad_phone = [0x12, 0x01, 0x68, 0x0a, 0x05, 0x2b];
+        let mut data = vec![0x08, 0x2a];
+        data.extend_from_slice(&[0x1a, phone.len() as u8]);
+        data.extend_from_slice(&phone);
+        data.extend_from_slice(&[0x1a, bad_phone.len() as u8]);
+        data.extend_from_slice(&bad_phone);
+
+        fn phone_field(field: Field) -> Result<(), Error> {
+            field.value.as_string()?;
+            Ok(())
+        }
+        let error = parse_message(&data, |field| match field.field_num {
+            3 => parse_message(field.value.as_bytes()?, phone_field),
+            _ => Ok(()),
+        })
+        .unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedEOF));
+        // The length of the truncated `number` is at offset 2 + 6 + 2 + 3 + 1.
+        assert_eq!(error.offset(), Some(14));
+        assert_eq!(error.path().unwrap().to_string(), "3[1].1");
+        assert_eq!(
+            error.to_string(),
+            "Unexpected EOF at offset 14 (field 3[1].1)"
+        );
+    }
+
+    #[test]
+    fn callback_error_location() {
+        // Field 1 appears twice, the second time with invalid UTF-8.
+        let data = [0x0a, 0x01, 0x61, 0x0a, 0x01, 0xff];
+        let error = parse_message(&data, |field| {
+            field.value.as_string()?;
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(matches!(error.kind(), Error::InvalidString));
+        assert_eq!(error.offset(), Some(3));
+        assert_eq!(error.path().unwrap().to_string(), "1[1]");
+
+        let error = parse_message(&[0x0f], |_| Ok(())).unwrap_err();
+        assert!(matches!(error.kind(), Error::InvalidWireType));
+        assert_eq!(error.path().unwrap().to_string(), "1");
+
+        let error = parse_message(&[0x08, 0x01, 0x80], |_| Ok(())).unwrap_err();
+        assert!(matches!(error.kind(), Error::InvalidVarint));
+        assert_eq!(error.offset(), Some(2));
+        assert_eq!(error.path().unwrap().to_string(), "<root>");
+    }
+
+    #[test]
+    fn unknown_fields() {
+        // Field 1 is known; fields 2 (with a non-minimal varint) and 4 are not.
+        let data = [0x0a, 0x01, 0x61, 0x10, 0x81, 0x00, 0x25, 1, 2, 3, 4];
+        let mut unknown = UnknownFields::default();
+        parse_message(&data, |field| {
+            match field.field_num {
+                1 => assert_eq!(field.value.as_string()?, "a"),
+                _ => unknown.push(field),
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(unknown.len(), 2);
+        let fields: Vec<_> = unknown.iter().copied().collect();
+        assert_eq!(
+            fields,
+            [
+                UnknownField {
+                    field_num: 2,
+                    wire_type: WireType::Varint,
+                    raw: &data[3..6],
+                },
+                UnknownField {
+                    field_num: 4,
+                    wire_type: WireType::I32,
+                    raw: &data[6..],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn zigzag() {
+        for (encoded, decoded) in [(0, 0), (1, -1), (2, 1), (3, -2), (4294967294, 2147483647)] {
+            assert_eq!(FieldValue::Varint(encoded).as_sint64().unwrap(), decoded);
+        }
+        assert_eq!(zigzag_decode(u64::MAX), i64::MIN);
+        assert_eq!(
+            FieldValue::Varint(4294967295).as_sint32().unwrap(),
+            i32::MIN
+        );
+    }
+
+    #[test]
+    fn packed_varints() {
+        // Field 4, Len, packed [3, 270, 86942].
+        let data = [0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05];
+        let (field, _) = parse_field(&data).unwrap();
+        let values: Vec<u64> = field
+            .value
+            .as_packed_varints()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(values, [3, 270, 86942]);
+
+        let mut truncated = PackedVarints(&[0x03, 0x8e]);
+        assert_eq!(truncated.next().unwrap().unwrap(), 3);
+        assert!(matches!(truncated.next(), Some(Err(Error::InvalidVarint))));
+        assert!(truncated.next().is_none());
+    }
+
+    #[test]
+    fn packed_fixed() {
+        let data = [1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        let values: Vec<u32> = PackedFixed32(&data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(values, [1, u32::MAX]);
+        let values: Vec<u64> = PackedFixed64(&data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(values, [0xffff_ffff_0000_0001]);
+
+        let mut truncated = PackedFixed64(&data[..7]);
+        assert!(matches!(truncated.next(), Some(Err(Error::UnexpectedEOF))));
+        assert!(truncated.next().is_none());
+        assert!(matches!(
+            FieldValue::Varint(1).as_packed_fixed32(),
+            Err(Error::UnexpectedWireType)
+        ));
+    }
+}
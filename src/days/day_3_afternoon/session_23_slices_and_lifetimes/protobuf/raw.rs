@@ -0,0 +1,184 @@
+use std::fmt::Write as _;
+
+use super::dynamic::DEFAULT_MAX_DEPTH;
+use super::parser::{parse_field, parse_message, Error, FieldValue};
+
+/// Dump a message without knowing its schema, like `protoc --decode_raw`.
+///
+/// Each field is printed on its own line as its field number, its wire type
+/// in brackets and its value, with nested messages indented:
+///
+/// ```text
+/// 1 [len]: "maxwell"
+/// 2 [varint]: 42
+/// 3 [len] {
+///   1 [len]: "+1202-555-1212"
+/// }
+/// ```
+///
+/// The contents of a Len field are guessed as `protoc` does: anything that
+/// parses as a message is shown as a nested message, anything else that is
+/// printable UTF-8 as a string, and the rest as escaped bytes. Malformed
+/// input is reported as an error.
+pub fn decode_raw(data: &[u8]) -> Result<String, Error> {
+    let mut out = String::new();
+    write_message(data, 0, &mut out)?;
+    Ok(out)
+}
+
+fn write_message(data: &[u8], depth: usize, out: &mut String) -> Result<(), Error> {
+    let indent = "  ".repeat(depth);
+    parse_message(data, |field| {
+        write!(out, "{indent}{}", field.field_num).unwrap();
+        match field.value {
+            FieldValue::Varint(value) => writeln!(out, " [varint]: {value}").unwrap(),
+            FieldValue::I64(value) => writeln!(out, " [i64]: 0x{value:016x}").unwrap(),
+            FieldValue::I32(value) => writeln!(out, " [i32]: 0x{value:08x}").unwrap(),
+            FieldValue::Len(payload) if depth + 1 < DEFAULT_MAX_DEPTH && is_message(payload) => {
+                writeln!(out, " [len] {{").unwrap();
+                write_message(payload, depth + 1, out)?;
+                writeln!(out, "{indent}}}").unwrap();
+            }
+            FieldValue::Len(payload) => match std::str::from_utf8(payload) {
+                Ok(text) if is_printable(text) => {
+                    writeln!(out, " [len]: {}", escape_str(text)).unwrap()
+                }
+                _ => writeln!(out, " [len]: {}", escape_bytes(payload)).unwrap(),
+            },
+        }
+        Ok(())
+    })
+}
+
+/// Whether `text` looks like a human-readable string rather than binary data.
+fn is_printable(text: &str) -> bool {
+    text.chars()
+        .all(|c| !c.is_control() || c == '\n' || c == '\t' || c == '\r')
+}
+
+/// Whether `data` is non-empty and parses as a sequence of valid fields.
+fn is_message(mut data: &[u8]) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+    while !data.is_empty() {
+        match parse_field(data) {
+            Ok((field, remainder)) if field.field_num != 0 => data = remainder,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Quote a printable string, escaping quotes and backslashes.
+fn escape_str(text: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Quote binary data, escaping quotes, backslashes and non-printable bytes.
+fn escape_bytes(data: &[u8]) -> String {
+    let mut escaped = String::from('"');
+    for &b in data {
+        match b {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(b as char),
+            _ => write!(escaped, "\\x{b:02x}").unwrap(),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::writer::MessageWriter;
+    use super::*;
+
+    #[test]
+    fn person() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1202-555-1212")?;
+                phone.write_string(2, "home")
+            })
+            .unwrap();
+        writer.write_double(4, 1.25).unwrap();
+        writer.write_i32(5, -1).unwrap();
+        writer.write_bytes(6, &[0x00, b'"', 0xff]).unwrap();
+        writer.write_string(7, "").unwrap();
+        writer
+            .write_message(8, |outer| {
+                outer.write_message(1, |inner| inner.write_varint(1, 1))
+            })
+            .unwrap();
+
+        assert_eq!(
+            decode_raw(&writer.into_inner()).unwrap(),
+            r#"1 [len]: "maxwell"
+2 [varint]: 42
+3 [len] {
+  1 [len]: "+1202-555-1212"
+  2 [len]: "home"
+}
+4 [i64]: 0x3ff4000000000000
+5 [i32]: 0xffffffff
+6 [len]: "\x00\"\xff"
+7 [len]: ""
+8 [len] {
+  1 [len] {
+    1 [varint]: 1
+  }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn printable_message() {
+        // The nested message starts with the tag 0x0a and a length of 32,
+        // which is a space, so all of its bytes are printable too.
+        let street = "1600 Pennsylvania Avenue NW, DC.";
+        let mut writer = MessageWriter::new(Vec::new());
+        writer
+            .write_message(1, |address| address.write_string(1, street))
+            .unwrap();
+        let data = writer.into_inner();
+        assert!(is_printable(std::str::from_utf8(&data[2..]).unwrap()));
+
+        assert_eq!(
+            decode_raw(&data).unwrap(),
+            format!("1 [len] {{\n  1 [len]: \"{street}\"\n}}\n")
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        // A Len field claiming 10 bytes, with only 2 present.
+        let error = decode_raw(&[0x08, 0x01, 0x12, 0x0a, 0x01, 0x02]).unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedEOF));
+        assert_eq!(error.offset(), Some(3));
+
+        // Garbage inside a Len field is shown as bytes rather than failing.
+        assert_eq!(
+            decode_raw(&[0x0a, 0x02, 0x0f, 0x01]).unwrap(),
+            "1 [len]: \"\\x0f\\x01\"\n"
+        );
+        assert!(decode_raw(&[0xff; 12]).is_err());
+    }
+}
@@ -0,0 +1,600 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use thiserror::Error;
+
+use super::parser::WireType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SchemaError {
+    #[error("Unexpected character '{1}' on line {0}")]
+    UnexpectedCharacter(usize, char),
+    #[error("Unexpected {1} on line {0}, expected {2}")]
+    UnexpectedToken(usize, String, &'static str),
+    #[error("Unexpected end of input")]
+    UnexpectedEOF,
+    #[error("Invalid field number {1} on line {0}")]
+    InvalidFieldNumber(usize, u64),
+    #[error("Field number {1} is used twice in message {0}")]
+    DuplicateFieldNumber(String, u64),
+    #[error("Message {0} is defined twice")]
+    DuplicateMessage(String),
+    #[error("Unknown type {1} in message {0}")]
+    UnknownType(String, String),
+}
+
+/// The scalar value types of the protobuf language.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarType {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    Bool,
+    String,
+    Bytes,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<ScalarType> {
+        Some(match name {
+            "double" => ScalarType::Double,
+            "float" => ScalarType::Float,
+            "int32" => ScalarType::Int32,
+            "int64" => ScalarType::Int64,
+            "uint32" => ScalarType::Uint32,
+            "uint64" => ScalarType::Uint64,
+            "sint32" => ScalarType::Sint32,
+            "sint64" => ScalarType::Sint64,
+            "fixed32" => ScalarType::Fixed32,
+            "fixed64" => ScalarType::Fixed64,
+            "sfixed32" => ScalarType::Sfixed32,
+            "sfixed64" => ScalarType::Sfixed64,
+            "bool" => ScalarType::Bool,
+            "string" => ScalarType::String,
+            "bytes" => ScalarType::Bytes,
+            _ => return None,
+        })
+    }
+
+    /// The wire type a single value of this type is encoded with.
+    pub fn wire_type(self) -> WireType {
+        match self {
+            ScalarType::Int32
+            | ScalarType::Int64
+            | ScalarType::Uint32
+            | ScalarType::Uint64
+            | ScalarType::Sint32
+            | ScalarType::Sint64
+            | ScalarType::Bool => WireType::Varint,
+            ScalarType::Double | ScalarType::Fixed64 | ScalarType::Sfixed64 => WireType::I64,
+            ScalarType::Float | ScalarType::Fixed32 | ScalarType::Sfixed32 => WireType::I32,
+            ScalarType::String | ScalarType::Bytes => WireType::Len,
+        }
+    }
+}
+
+/// The type of a field: either a scalar, or a reference to another message
+/// by its fully-qualified name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Scalar(ScalarType),
+    Message(String),
+}
+
+/// How many times a field may occur in a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cardinality {
+    /// A field without a label (proto3), or a `required` field (proto2).
+    Singular,
+    /// A field labelled `optional`.
+    Optional,
+    /// A field labelled `repeated`.
+    Repeated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub number: u64,
+    pub cardinality: Cardinality,
+    pub field_type: FieldType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageDescriptor {
+    /// The fully-qualified name, e.g. `Person.PhoneNumber` for a nested message.
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+impl MessageDescriptor {
+    /// Look up a field by its field number.
+    pub fn field(&self, number: u64) -> Option<&FieldDescriptor> {
+        self.fields.iter().find(|f| f.number == number)
+    }
+
+    /// Look up a field by its name.
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldDescriptor> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// The messages defined in a `.proto` file.
+#[derive(Debug, Default)]
+pub struct Schema {
+    messages: HashMap<String, MessageDescriptor>,
+}
+
+impl Schema {
+    /// Parse the text of a `.proto` file.
+    ///
+    /// Only `message` definitions (possibly nested) with scalar and message
+    /// typed fields are supported. `syntax` and `package` statements are
+    /// accepted and ignored, as are field options in square brackets.
+    pub fn parse(source: &str) -> Result<Schema, SchemaError> {
+        let mut parser = ProtoParser {
+            tokens: ProtoLexer {
+                chars: source.chars().peekable(),
+                line: 1,
+            }
+            .peekable(),
+            schema: Schema::default(),
+        };
+        parser.parse_file()?;
+        let mut schema = parser.schema;
+        schema.resolve_types()?;
+        Ok(schema)
+    }
+
+    /// Look up a message by its fully-qualified name.
+    pub fn message(&self, name: &str) -> Option<&MessageDescriptor> {
+        self.messages.get(name)
+    }
+
+    /// Rewrite every message reference into a fully-qualified name, searching
+    /// from the innermost enclosing scope outwards like `protoc` does. Names
+    /// with a leading `.` are already fully qualified.
+    fn resolve_types(&mut self) -> Result<(), SchemaError> {
+        let mut resolved = Vec::new();
+        for message in self.messages.values() {
+            for (i, field) in message.fields.iter().enumerate() {
+                let FieldType::Message(type_name) = &field.field_type else {
+                    continue;
+                };
+                let (mut scope, name) = match type_name.strip_prefix('.') {
+                    Some(name) => (None, name),
+                    None => (Some(message.name.as_str()), type_name.as_str()),
+                };
+                let full_name = loop {
+                    let candidate = match scope {
+                        Some(scope) => format!("{scope}.{name}"),
+                        None => name.to_string(),
+                    };
+                    if self.messages.contains_key(&candidate) {
+                        break candidate;
+                    }
+                    scope = match scope {
+                        Some(scope) => scope.rsplit_once('.').map(|(outer, _)| outer),
+                        None => {
+                            return Err(SchemaError::UnknownType(
+                                message.name.clone(),
+                                type_name.clone(),
+                            ))
+                        }
+                    };
+                };
+                resolved.push((message.name.clone(), i, full_name));
+            }
+        }
+        for (message, i, full_name) in resolved {
+            self.messages.get_mut(&message).unwrap().fields[i].field_type =
+                FieldType::Message(full_name);
+        }
+        Ok(())
+    }
+}
+
+/// A token in a `.proto` file.
+#[derive(Debug, PartialEq)]
+enum ProtoToken {
+    Identifier(String),
+    Integer(u64),
+    String(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for ProtoToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProtoToken::Identifier(ident) => write!(f, "'{ident}'"),
+            ProtoToken::Integer(value) => write!(f, "{value}"),
+            ProtoToken::String(value) => write!(f, "{value:?}"),
+            ProtoToken::Symbol(c) => write!(f, "'{c}'"),
+        }
+    }
+}
+
+struct ProtoLexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> ProtoLexer<'a> {
+    /// Skip whitespace and comments.
+    fn skip_trivia(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '\n' => {
+                    self.line += 1;
+                    self.chars.next();
+                }
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                '/' => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    match lookahead.next() {
+                        Some('/') => while self.chars.next_if(|&c| c != '\n').is_some() {},
+                        Some('*') => {
+                            self.chars.next();
+                            self.chars.next();
+                            let mut prev = ' ';
+                            for c in self.chars.by_ref() {
+                                if c == '\n' {
+                                    self.line += 1;
+                                }
+                                if prev == '*' && c == '/' {
+                                    break;
+                                }
+                                prev = c;
+                            }
+                        }
+                        _ => return,
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ProtoLexer<'a> {
+    type Item = Result<(usize, ProtoToken), SchemaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_trivia();
+        let c = self.chars.next()?;
+        let line = self.line;
+        let token = match c {
+            'a'..='z' | 'A'..='Z' | '_' | '.' => {
+                let mut ident = String::from(c);
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    ident.push(c);
+                }
+                ProtoToken::Identifier(ident)
+            }
+            '0'..='9' => {
+                let mut value = c.to_digit(10).unwrap() as u64;
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit()) {
+                    value = value
+                        .saturating_mul(10)
+                        .saturating_add(c.to_digit(10).unwrap() as u64);
+                }
+                ProtoToken::Integer(value)
+            }
+            '"' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\n') | None => return Some(Err(SchemaError::UnexpectedEOF)),
+                        Some(c) => value.push(c),
+                    }
+                }
+                ProtoToken::String(value)
+            }
+            '{' | '}' | '=' | ';' | '[' | ']' | ',' => ProtoToken::Symbol(c),
+            _ => return Some(Err(SchemaError::UnexpectedCharacter(line, c))),
+        };
+        Some(Ok((line, token)))
+    }
+}
+
+struct ProtoParser<'a> {
+    tokens: Peekable<ProtoLexer<'a>>,
+    schema: Schema,
+}
+
+impl<'a> ProtoParser<'a> {
+    fn next_token(&mut self) -> Result<(usize, ProtoToken), SchemaError> {
+        self.tokens.next().ok_or(SchemaError::UnexpectedEOF)?
+    }
+
+    fn expect_symbol(&mut self, symbol: char, expected: &'static str) -> Result<(), SchemaError> {
+        match self.next_token()? {
+            (_, ProtoToken::Symbol(c)) if c == symbol => Ok(()),
+            (line, tok) => Err(SchemaError::UnexpectedToken(
+                line,
+                tok.to_string(),
+                expected,
+            )),
+        }
+    }
+
+    fn expect_identifier(&mut self, expected: &'static str) -> Result<String, SchemaError> {
+        match self.next_token()? {
+            (_, ProtoToken::Identifier(ident)) => Ok(ident),
+            (line, tok) => Err(SchemaError::UnexpectedToken(
+                line,
+                tok.to_string(),
+                expected,
+            )),
+        }
+    }
+
+    fn parse_file(&mut self) -> Result<(), SchemaError> {
+        while self.tokens.peek().is_some() {
+            let (line, keyword) = self.next_token()?;
+            match keyword {
+                ProtoToken::Identifier(ident) if ident == "syntax" => {
+                    self.expect_symbol('=', "'='")?;
+                    match self.next_token()? {
+                        (_, ProtoToken::String(_)) => {}
+                        (line, tok) => {
+                            return Err(SchemaError::UnexpectedToken(
+                                line,
+                                tok.to_string(),
+                                "a string",
+                            ))
+                        }
+                    }
+                    self.expect_symbol(';', "';'")?;
+                }
+                ProtoToken::Identifier(ident) if ident == "package" => {
+                    self.expect_identifier("a package name")?;
+                    self.expect_symbol(';', "';'")?;
+                }
+                ProtoToken::Identifier(ident) if ident == "message" => {
+                    self.parse_message(None)?;
+                }
+                ProtoToken::Symbol(';') => {}
+                tok => {
+                    return Err(SchemaError::UnexpectedToken(
+                        line,
+                        tok.to_string(),
+                        "'message'",
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a message definition, after the `message` keyword.
+    fn parse_message(&mut self, scope: Option<&str>) -> Result<(), SchemaError> {
+        let name = self.expect_identifier("a message name")?;
+        let name = match scope {
+            Some(scope) => format!("{scope}.{name}"),
+            None => name,
+        };
+        self.expect_symbol('{', "'{'")?;
+
+        let mut fields: Vec<FieldDescriptor> = Vec::new();
+        loop {
+            let (line, tok) = self.next_token()?;
+            let mut type_name = match tok {
+                ProtoToken::Symbol('}') => break,
+                ProtoToken::Symbol(';') => continue,
+                ProtoToken::Identifier(ident) if ident == "message" => {
+                    self.parse_message(Some(&name))?;
+                    continue;
+                }
+                ProtoToken::Identifier(ident) => ident,
+                tok => {
+                    return Err(SchemaError::UnexpectedToken(
+                        line,
+                        tok.to_string(),
+                        "a field or '}'",
+                    ))
+                }
+            };
+
+            let label = match type_name.as_str() {
+                "optional" => Some(Cardinality::Optional),
+                "repeated" => Some(Cardinality::Repeated),
+                "required" => Some(Cardinality::Singular),
+                _ => None,
+            };
+            let cardinality = match label {
+                Some(cardinality) => {
+                    type_name = self.expect_identifier("a field type")?;
+                    cardinality
+                }
+                None => Cardinality::Singular,
+            };
+            let field_type = match ScalarType::from_name(&type_name) {
+                Some(scalar) => FieldType::Scalar(scalar),
+                None => FieldType::Message(type_name),
+            };
+
+            let field_name = self.expect_identifier("a field name")?;
+            self.expect_symbol('=', "'='")?;
+            let number = match self.next_token()? {
+                (line, ProtoToken::Integer(number)) => {
+                    if !(1..=(1 << 29) - 1).contains(&number) {
+                        return Err(SchemaError::InvalidFieldNumber(line, number));
+                    }
+                    number
+                }
+                (line, tok) => {
+                    return Err(SchemaError::UnexpectedToken(
+                        line,
+                        tok.to_string(),
+                        "a field number",
+                    ))
+                }
+            };
+            if fields.iter().any(|f| f.number == number) {
+                return Err(SchemaError::DuplicateFieldNumber(name, number));
+            }
+
+            // Field options such as `[packed = true]` do not affect decoding.
+            if let Some(Ok((_, ProtoToken::Symbol('[')))) = self.tokens.peek() {
+                while self.next_token()?.1 != ProtoToken::Symbol(']') {}
+            }
+            self.expect_symbol(';', "';'")?;
+
+            fields.push(FieldDescriptor {
+                name: field_name,
+                number,
+                cardinality,
+                field_type,
+            });
+        }
+
+        if self.schema.messages.contains_key(&name) {
+            return Err(SchemaError::DuplicateMessage(name));
+        }
+        self.schema
+            .messages
+            .insert(name.clone(), MessageDescriptor { name, fields });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn person() {
+        let schema = Schema::parse(
+            r#"
+            syntax = "proto3";
+
+            // A phone number.
+            message PhoneNumber {
+                optional string number = 1;
+                optional string type = 2;
+            }
+
+            /* A person,
+               with phone numbers. */
+            message Person {
+                optional string name = 1;
+                optional int32 id = 2;
+                repeated PhoneNumber phones = 3 [packed = false];
+            }
+            "#,
+        )
+        .unwrap();
+
+        let person = schema.message("Person").unwrap();
+        assert_eq!(person.fields.len(), 3);
+        assert_eq!(
+            person.field(3),
+            Some(&FieldDescriptor {
+                name: "phones".to_string(),
+                number: 3,
+                cardinality: Cardinality::Repeated,
+                field_type: FieldType::Message("PhoneNumber".to_string()),
+            })
+        );
+        assert_eq!(
+            person.field_by_name("id").unwrap().field_type,
+            FieldType::Scalar(ScalarType::Int32)
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let schema = Schema::parse(
+            "message Outer {
+                message Inner { sint64 value = 1; }
+                Inner inner = 1;
+                repeated Outer children = 2;
+            }
+            message Other { Outer.Inner inner = 1; }",
+        )
+        .unwrap();
+        let shadowed = Schema::parse(
+            "message Inner { string name = 1; }
+            message Outer {
+                message Inner { sint64 value = 1; }
+                Inner inner = 1;
+                .Inner top = 2;
+                .Outer.Inner absolute = 3;
+            }",
+        )
+        .unwrap();
+        let shadowed_outer = shadowed.message("Outer").unwrap();
+        assert_eq!(
+            shadowed_outer.field(1).unwrap().field_type,
+            FieldType::Message("Outer.Inner".to_string())
+        );
+        assert_eq!(
+            shadowed_outer.field(2).unwrap().field_type,
+            FieldType::Message("Inner".to_string())
+        );
+        assert_eq!(
+            shadowed_outer.field(3).unwrap().field_type,
+            FieldType::Message("Outer.Inner".to_string())
+        );
+        let outer = schema.message("Outer").unwrap();
+        assert_eq!(
+            outer.field(1).unwrap().field_type,
+            FieldType::Message("Outer.Inner".to_string())
+        );
+        assert_eq!(
+            outer.field(2).unwrap().field_type,
+            FieldType::Message("Outer".to_string())
+        );
+        let other = schema.message("Other").unwrap();
+        assert_eq!(
+            other.field(1).unwrap().field_type,
+            FieldType::Message("Outer.Inner".to_string())
+        );
+        assert!(schema.message("Outer.Inner").is_some());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Schema::parse("message A { Missing m = 1; }").unwrap_err(),
+            SchemaError::UnknownType("A".to_string(), "Missing".to_string())
+        );
+        assert_eq!(
+            Schema::parse("message A { message B {} .B b = 1; }").unwrap_err(),
+            SchemaError::UnknownType("A".to_string(), ".B".to_string())
+        );
+        assert_eq!(
+            Schema::parse("message A { int32 a = 1; int32 b = 1; }").unwrap_err(),
+            SchemaError::DuplicateFieldNumber("A".to_string(), 1)
+        );
+        assert_eq!(
+            Schema::parse("message A {\n int32 a = 0; }").unwrap_err(),
+            SchemaError::InvalidFieldNumber(2, 0)
+        );
+        assert_eq!(
+            Schema::parse("message A {\n int32 a 1; }").unwrap_err(),
+            SchemaError::UnexpectedToken(2, "1".to_string(), "'='")
+        );
+        assert_eq!(
+            Schema::parse("message A { int32 a = 1;").unwrap_err(),
+            SchemaError::UnexpectedEOF
+        );
+    }
+}
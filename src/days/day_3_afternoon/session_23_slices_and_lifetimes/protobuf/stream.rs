@@ -0,0 +1,189 @@
+use std::io::BufRead;
+
+use super::dynamic::{decode_with_max_depth, DynamicMessage, DEFAULT_MAX_DEPTH};
+use super::parser::Error;
+use super::schema::{MessageDescriptor, Schema};
+
+/// The default limit on the size of a single message, as in the reference
+/// implementation.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+/// Reads a stream of length-delimited messages, each prefixed by its length
+/// as a VARINT, one message at a time.
+///
+/// Only the current message is held in memory. Its buffer is reused for the
+/// next one, so messages borrow from the reader.
+pub struct DelimitedReader<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+    max_message_size: usize,
+    max_depth: usize,
+}
+
+impl<R: BufRead> DelimitedReader<R> {
+    pub fn new(reader: R) -> Self {
+        DelimitedReader {
+            reader,
+            buf: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Set the largest message size accepted, in bytes.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Set the deepest nesting of messages accepted by `next_dynamic`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Read the length prefix of the next message, or `None` at the end of
+    /// the stream.
+    fn read_length(&mut self) -> Result<Option<u64>, Error> {
+        let mut value = 0u64;
+        for i in 0..10 {
+            let Some(&b) = self.reader.fill_buf()?.first() else {
+                return if i == 0 {
+                    Ok(None)
+                } else {
+                    Err(Error::UnexpectedEOF)
+                };
+            };
+            self.reader.consume(1);
+            if i == 9 && b > 1 {
+                return Err(Error::InvalidVarint);
+            }
+            value |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(Some(value));
+            }
+        }
+        Err(Error::InvalidVarint)
+    }
+
+    /// Read the next message, returning `None` if the stream ends cleanly
+    /// between messages.
+    pub fn next_message(&mut self) -> Result<Option<&[u8]>, Error> {
+        let Some(len) = self.read_length()? else {
+            return Ok(None);
+        };
+        if len > self.max_message_size as u64 {
+            return Err(Error::MessageTooLarge(len, self.max_message_size));
+        }
+        self.buf.resize(len as usize, 0);
+        self.reader
+            .read_exact(&mut self.buf)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => Error::UnexpectedEOF,
+                _ => Error::Io(e),
+            })?;
+        Ok(Some(&self.buf))
+    }
+
+    /// Read and decode the next message, of type `descriptor`.
+    pub fn next_dynamic(
+        &mut self,
+        schema: &Schema,
+        descriptor: &MessageDescriptor,
+    ) -> Result<Option<DynamicMessage<'_>>, Error> {
+        let max_depth = self.max_depth;
+        let Some(data) = self.next_message()? else {
+            return Ok(None);
+        };
+        decode_with_max_depth(schema, descriptor, data, max_depth).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::dynamic::Value;
+    use super::super::writer::{write_delimited, MessageWriter};
+    use super::*;
+
+    fn person(name: &str, id: u64) -> Vec<u8> {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, name).unwrap();
+        writer.write_varint(2, id).unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn messages() {
+        let mut stream = Vec::new();
+        write_delimited(&mut stream, &person("maxwell", 42)).unwrap();
+        write_delimited(&mut stream, &[]).unwrap();
+        let long_name = "x".repeat(300);
+        write_delimited(&mut stream, &person(&long_name, 7)).unwrap();
+
+        // A tiny buffer makes the length prefix span several `fill_buf` calls.
+        let mut reader = DelimitedReader::new(std::io::BufReader::with_capacity(1, &stream[..]));
+        assert_eq!(
+            reader.next_message().unwrap().unwrap(),
+            person("maxwell", 42)
+        );
+        assert_eq!(reader.next_message().unwrap().unwrap(), &[]);
+        assert_eq!(
+            reader.next_message().unwrap().unwrap(),
+            person(&long_name, 7)
+        );
+        assert!(reader.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn dynamic() {
+        let schema = Schema::parse("message Person { string name = 1; uint64 id = 2; }").unwrap();
+        let descriptor = schema.message("Person").unwrap();
+        let mut stream = Vec::new();
+        for id in 0..3 {
+            write_delimited(&mut stream, &person("maxwell", id)).unwrap();
+        }
+
+        let mut reader = DelimitedReader::new(&stream[..]);
+        let mut ids = Vec::new();
+        while let Some(message) = reader.next_dynamic(&schema, descriptor).unwrap() {
+            let Some(&Value::Uint64(id)) = message.get("id") else {
+                panic!("id should be a uint64");
+            };
+            ids.push(id);
+        }
+        assert_eq!(ids, [0, 1, 2]);
+    }
+
+    #[test]
+    fn limits() {
+        let mut stream = Vec::new();
+        write_delimited(&mut stream, &person("maxwell", 42)).unwrap();
+        let mut reader = DelimitedReader::new(&stream[..]).max_message_size(8);
+        assert!(matches!(
+            reader.next_message(),
+            Err(Error::MessageTooLarge(11, 8))
+        ));
+
+        let schema = Schema::parse("message Node { Node child = 1; }").unwrap();
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_message(1, |_| Ok(())).unwrap();
+        let mut stream = Vec::new();
+        write_delimited(&mut stream, &writer.into_inner()).unwrap();
+        let mut reader = DelimitedReader::new(&stream[..]).max_depth(1);
+        let error = reader
+            .next_dynamic(&schema, schema.message("Node").unwrap())
+            .unwrap_err();
+        assert!(matches!(error.kind(), Error::RecursionLimit));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut stream = Vec::new();
+        write_delimited(&mut stream, &person("maxwell", 42)).unwrap();
+        let mut reader = DelimitedReader::new(&stream[..5]);
+        assert!(matches!(reader.next_message(), Err(Error::UnexpectedEOF)));
+
+        let mut reader = DelimitedReader::new(&[0x80, 0x80][..]);
+        assert!(matches!(reader.next_message(), Err(Error::UnexpectedEOF)));
+    }
+}
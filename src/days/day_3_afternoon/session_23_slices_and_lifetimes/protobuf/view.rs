@@ -0,0 +1,86 @@
+use std::marker::PhantomData;
+
+use super::parser::{parse_field, Error, FieldValue, PathSegment};
+
+/// A message type that can be decoded from its encoding, typically as a
+/// view borrowing strings, bytes and nested messages from `data`.
+pub trait Message<'a>: Sized {
+    fn decode(data: &'a [u8]) -> Result<Self, Error>;
+}
+
+/// A lazy iterator over the values of a repeated message field.
+///
+/// Nothing is decoded until the iterator is advanced, so views can hold one
+/// for each repeated field at no cost. Each call to `next` scans forward to
+/// the next occurrence of the field and decodes it as an `M`.
+pub struct RepeatedIter<'a, M> {
+    /// The encoding of the message containing the field.
+    data: &'a [u8],
+    /// The offset in `data` of the next field to look at.
+    offset: usize,
+    field_num: u64,
+    /// The number of occurrences of the field seen so far.
+    index: usize,
+    message: PhantomData<fn() -> M>,
+}
+
+impl<'a, M> RepeatedIter<'a, M> {
+    /// Iterate over the occurrences of field `field_num` in the message
+    /// encoded in `data`.
+    pub fn new(data: &'a [u8], field_num: u64) -> Self {
+        RepeatedIter {
+            data,
+            offset: 0,
+            field_num,
+            index: 0,
+            message: PhantomData,
+        }
+    }
+}
+
+// Derived `Clone` would require `M: Clone`.
+impl<'a, M> Clone for RepeatedIter<'a, M> {
+    fn clone(&self) -> Self {
+        RepeatedIter { ..*self }
+    }
+}
+
+impl<'a, M: Message<'a>> Iterator for RepeatedIter<'a, M> {
+    type Item = Result<M, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.data.len() {
+            let field_offset = self.offset;
+            let (field, remainder) = match parse_field(&self.data[field_offset..]) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    self.offset = self.data.len();
+                    return Some(Err(e.shifted(field_offset)));
+                }
+            };
+            self.offset = self.data.len() - remainder.len();
+            if field.field_num != self.field_num {
+                continue;
+            }
+
+            let segment = PathSegment {
+                field_num: self.field_num,
+                index: self.index,
+            };
+            self.index += 1;
+            let FieldValue::Len(payload) = field.value else {
+                return Some(Err(Error::UnexpectedWireType.within(
+                    segment,
+                    field_offset,
+                    field_offset,
+                )));
+            };
+            // A Len payload is always the last part of the field.
+            let payload_offset = self.offset - payload.len();
+            return Some(
+                M::decode(payload).map_err(|e| e.within(segment, field_offset, payload_offset)),
+            );
+        }
+        None
+    }
+}
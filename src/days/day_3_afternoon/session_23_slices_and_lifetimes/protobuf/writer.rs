@@ -0,0 +1,356 @@
+use std::io::Write;
+
+use super::parser::{Error, Field, FieldValue, UnknownField, UnknownFields, WireType};
+
+/// Encode a VARINT, appending it to `buf`.
+pub fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// ZigZag-encode a signed integer, as used by `sint32` and `sint64`. This
+/// is the inverse of `zigzag_decode`.
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// The number of bytes `encode_varint` produces for `value`.
+pub fn varint_len(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.max(1).div_ceil(7)
+}
+
+/// Convert a field number and a WireType into a tag. This is the inverse of
+/// `unpack_tag`.
+pub fn pack_tag(field_num: u64, wire_type: WireType) -> u64 {
+    let wire_type = match wire_type {
+        WireType::Varint => 0,
+        WireType::I64 => 1,
+        WireType::Len => 2,
+        WireType::I32 => 5,
+    };
+    (field_num << 3) | wire_type
+}
+
+/// Write `message` prefixed by its length as a VARINT, the framing used to
+/// store several messages in one stream. This is the inverse of
+/// `DelimitedReader::next_message`.
+pub fn write_delimited(out: &mut impl Write, message: &[u8]) -> Result<(), Error> {
+    let mut buf = Vec::with_capacity(10);
+    encode_varint(message.len() as u64, &mut buf);
+    out.write_all(&buf)?;
+    out.write_all(message)?;
+    Ok(())
+}
+
+/// Writes the fields of a message, one after the next, into `W`.
+///
+/// Nested messages are first encoded into a temporary buffer, since their
+/// length must be written before their contents.
+pub struct MessageWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> MessageWriter<W> {
+    pub fn new(out: W) -> Self {
+        MessageWriter { out }
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_raw_varint(&mut self, value: u64) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(10);
+        encode_varint(value, &mut buf);
+        self.out.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn write_tag(&mut self, field_num: u64, wire_type: WireType) -> Result<(), Error> {
+        self.write_raw_varint(pack_tag(field_num, wire_type))
+    }
+
+    /// Write a field with the wire type of its value.
+    ///
+    /// The field is re-encoded from its value. Use `write_unknown` to write a
+    /// field exactly as it was parsed.
+    pub fn write_field(&mut self, field: &Field) -> Result<(), Error> {
+        self.write_value(field.field_num, &field.value)
+    }
+
+    /// Write a value with its wire type.
+    pub fn write_value(&mut self, field_num: u64, value: &FieldValue) -> Result<(), Error> {
+        match *value {
+            FieldValue::Varint(value) => self.write_varint(field_num, value),
+            FieldValue::I64(value) => self.write_i64(field_num, value),
+            FieldValue::Len(data) => self.write_bytes(field_num, data),
+            FieldValue::I32(value) => self.write_i32(field_num, value),
+        }
+    }
+
+    /// Write an unknown field back byte-for-byte.
+    pub fn write_unknown(&mut self, field: &UnknownField) -> Result<(), Error> {
+        self.out.write_all(field.raw)?;
+        Ok(())
+    }
+
+    /// Write all unknown fields of a message, in their original order.
+    pub fn write_unknown_fields(&mut self, fields: &UnknownFields) -> Result<(), Error> {
+        for field in fields.iter() {
+            self.write_unknown(field)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_varint(&mut self, field_num: u64, value: u64) -> Result<(), Error> {
+        self.write_tag(field_num, WireType::Varint)?;
+        self.write_raw_varint(value)
+    }
+
+    /// Write an `int64`, where negative values are stored in two's complement.
+    pub fn write_int64(&mut self, field_num: u64, value: i64) -> Result<(), Error> {
+        self.write_varint(field_num, value as u64)
+    }
+
+    /// Write a ZigZag-encoded `sint32` or `sint64`.
+    pub fn write_sint64(&mut self, field_num: u64, value: i64) -> Result<(), Error> {
+        self.write_varint(field_num, zigzag_encode(value))
+    }
+
+    pub fn write_i64(&mut self, field_num: u64, value: i64) -> Result<(), Error> {
+        self.write_tag(field_num, WireType::I64)?;
+        self.out.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn write_fixed64(&mut self, field_num: u64, value: u64) -> Result<(), Error> {
+        self.write_i64(field_num, value as i64)
+    }
+
+    pub fn write_double(&mut self, field_num: u64, value: f64) -> Result<(), Error> {
+        self.write_fixed64(field_num, value.to_bits())
+    }
+
+    pub fn write_i32(&mut self, field_num: u64, value: i32) -> Result<(), Error> {
+        self.write_tag(field_num, WireType::I32)?;
+        self.out.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, field_num: u64, data: &[u8]) -> Result<(), Error> {
+        self.write_tag(field_num, WireType::Len)?;
+        self.write_raw_varint(data.len() as u64)?;
+        self.out.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn write_string(&mut self, field_num: u64, value: &str) -> Result<(), Error> {
+        self.write_bytes(field_num, value.as_bytes())
+    }
+
+    /// Write a packed repeated field of VARINTs.
+    pub fn write_packed_varints(
+        &mut self,
+        field_num: u64,
+        values: impl IntoIterator<Item = u64>,
+    ) -> Result<(), Error> {
+        let mut data = Vec::new();
+        for value in values {
+            encode_varint(value, &mut data);
+        }
+        self.write_bytes(field_num, &data)
+    }
+
+    /// Write a packed repeated field of 4-byte values.
+    pub fn write_packed_fixed32(
+        &mut self,
+        field_num: u64,
+        values: impl IntoIterator<Item = u32>,
+    ) -> Result<(), Error> {
+        let data: Vec<u8> = values.into_iter().flat_map(u32::to_le_bytes).collect();
+        self.write_bytes(field_num, &data)
+    }
+
+    /// Write a packed repeated field of 8-byte values.
+    pub fn write_packed_fixed64(
+        &mut self,
+        field_num: u64,
+        values: impl IntoIterator<Item = u64>,
+    ) -> Result<(), Error> {
+        let data: Vec<u8> = values.into_iter().flat_map(u64::to_le_bytes).collect();
+        self.write_bytes(field_num, &data)
+    }
+
+    /// Write a nested message, whose fields are written by `write_fields`.
+    pub fn write_message(
+        &mut self,
+        field_num: u64,
+        write_fields: impl FnOnce(&mut MessageWriter<Vec<u8>>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut nested = MessageWriter::new(Vec::new());
+        write_fields(&mut nested)?;
+        self.write_bytes(field_num, &nested.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parser::{
+        parse_field, parse_message, parse_varint, unpack_tag, zigzag_decode, UnknownFields,
+    };
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            assert_eq!(buf.len(), varint_len(value));
+            assert_eq!(parse_varint(&buf).unwrap(), (value, &[][..]));
+        }
+    }
+
+    #[test]
+    fn zigzag_round_trip() {
+        for value in [
+            0,
+            1,
+            -1,
+            2,
+            -2,
+            i32::MAX as i64,
+            i32::MIN as i64,
+            i64::MAX,
+            i64::MIN,
+        ] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+        }
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+    }
+
+    #[test]
+    fn tag_round_trip() {
+        for wire_type in [
+            WireType::Varint,
+            WireType::I64,
+            WireType::Len,
+            WireType::I32,
+        ] {
+            let (field_num, unpacked) = unpack_tag(pack_tag(42, wire_type)).unwrap();
+            assert_eq!(field_num, 42);
+            assert_eq!(unpacked, wire_type);
+        }
+    }
+
+    #[test]
+    fn field_round_trip() {
+        let fields = [
+            (1, FieldValue::Varint(150)),
+            (2, FieldValue::I64(-7)),
+            (3, FieldValue::Len(b"hello")),
+            (4, FieldValue::I32(i32::MIN)),
+        ];
+        let mut writer = MessageWriter::new(Vec::new());
+        for (field_num, value) in &fields {
+            writer.write_value(*field_num, value).unwrap();
+        }
+        let data = writer.into_inner();
+
+        let mut parsed = Vec::new();
+        parse_message(&data, |field| {
+            parsed.push((field.field_num, field.value));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(parsed, fields);
+
+        let mut writer = MessageWriter::new(Vec::new());
+        parse_message(&data, |field| writer.write_field(&field)).unwrap();
+        assert_eq!(writer.into_inner(), data);
+    }
+
+    #[test]
+    fn unknown_round_trip() {
+        // Written by a newer schema, which added fields 4 and 5 to a Person.
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer.write_double(4, 1.25).unwrap();
+        writer
+            .write_message(5, |address| address.write_string(1, "Main St"))
+            .unwrap();
+        let mut data = writer.into_inner();
+        // A non-minimal varint must also survive unchanged.
+        data.extend_from_slice(&[0x30, 0x80, 0x80, 0x00]);
+
+        // An older proxy only knows fields 1 and 2, and rewrites the name.
+        let mut id = 0;
+        let mut unknown = UnknownFields::default();
+        parse_message(&data, |field| {
+            match field.field_num {
+                1 => {}
+                2 => id = field.value.as_u64()?,
+                _ => unknown.push(field),
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(unknown.len(), 3);
+
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, id).unwrap();
+        writer.write_unknown_fields(&unknown).unwrap();
+        assert_eq!(writer.into_inner(), data);
+    }
+
+    #[test]
+    fn person() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1202-555-1212")?;
+                phone.write_string(2, "home")
+            })
+            .unwrap();
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1800-867-5308")?;
+                phone.write_string(2, "mobile")
+            })
+            .unwrap();
+        let data = writer.into_inner();
+
+        assert_eq!(
+            data,
+            [
+                0x0a, 0x07, 0x6d, 0x61, 0x78, 0x77, 0x65, 0x6c, 0x6c, 0x10, 0x2a, 0x1a, 0x16, 0x0a,
+                0x0e, 0x2b, 0x31, 0x32, 0x30, 0x32, 0x2d, 0x35, 0x35, 0x35, 0x2d, 0x31, 0x32, 0x31,
+                0x32, 0x12, 0x04, 0x68, 0x6f, 0x6d, 0x65, 0x1a, 0x18, 0x0a, 0x0e, 0x2b, 0x31, 0x38,
+                0x30, 0x30, 0x2d, 0x38, 0x36, 0x37, 0x2d, 0x35, 0x33, 0x30, 0x38, 0x12, 0x06, 0x6d,
+                0x6f, 0x62, 0x69, 0x6c, 0x65,
+            ]
+        );
+
+        let (field, _) = parse_field(&data).unwrap();
+        assert_eq!(field.value.as_string().unwrap(), "maxwell");
+    }
+
+    #[test]
+    fn write_to_io() {
+        let mut out = std::io::Cursor::new(Vec::new());
+        let mut writer = MessageWriter::new(&mut out);
+        writer.write_double(1, 2.5).unwrap();
+        let data = out.into_inner();
+        let (field, _) = parse_field(&data).unwrap();
+        assert_eq!(field.value.as_f64().unwrap(), 2.5);
+    }
+}
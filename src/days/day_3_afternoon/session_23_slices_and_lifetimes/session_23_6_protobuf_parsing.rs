@@ -34,220 +34,255 @@
 ///  stream.
 ///
 /// Integers, including the tag, are represented with a variable-length
-/// encoding called `VARINT`. Luckily, parse_varint is defined for you
-/// below. The given code also defines callbacks to handle `Person` and
-/// `PhoneNumber` fields, and to parse a message into a series of calls to
+/// encoding called `VARINT`. The parsing itself lives in the `protobuf`
+/// module: `parse_varint`, `parse_field` and `parse_message` are in
+/// `protobuf/parser.rs`, and the `MessageWriter` producing the encoding is
+/// in `protobuf/writer.rs`. This file defines callbacks to handle `Person`
+/// and `PhoneNumber` fields, and parses a message into a series of calls to
 /// those callbacks.
 ///
-/// What remains for you is to implement the `parse_field` function.
-///
-use std::convert::TryFrom;
-use thiserror::Error;
-
-#[derive(Debug, Error)]
-enum Error {
-    #[error("Invalid varint")]
-    InvalidVarint,
-    #[error("Invalid wire-type")]
-    InvalidWireType,
-    #[error("Unexpected EOF")]
-    UnexpectedEOF,
-    #[error("Invalid length")]
-    InvalidSize(#[from] std::num::TryFromIntError),
-    #[error("Unexpected wire-type)")]
-    UnexpectedWireType,
-    #[error("Invalid string (not UTF-8)")]
-    InvalidString,
-}
+use std::io::Write;
 
-/// A wire type as seen on the wire.
-enum WireType {
-    /// The Varint WireType indicates the value is a single VARINT.
-    Varint,
-    //I64,  -- not needed for this exercise
-    /// The Len WireType indicates that the value is a length represented as a VARINT
-    /// followed by exactly that number of bytes.
-    Len,
-    /// The I32 WireType indicates that the value is precisely 4 bytes in little-endian order
-    /// containing a 32-bit signed integer.
-    I32,
-}
+use super::protobuf::parser::{parse_message, Error, Field, UnknownFields};
+use super::protobuf::view::{Message, RepeatedIter};
+use super::protobuf::writer::MessageWriter;
 
-#[derive(Debug)]
-/// A field's value, typed based on the wire type.
-enum FieldValue<'a> {
-    Varint(u64),
-    //I64(i64),  -- not needed for this exercise
-    Len(&'a [u8]),
-    I32(i32),
-}
+/// A Person message with two phone numbers.
+const PERSON: &[u8] = &[
+    0x0a, 0x07, 0x6d, 0x61, 0x78, 0x77, 0x65, 0x6c, 0x6c, 0x10, 0x2a, 0x1a, 0x16, 0x0a, 0x0e, 0x2b,
+    0x31, 0x32, 0x30, 0x32, 0x2d, 0x35, 0x35, 0x35, 0x2d, 0x31, 0x32, 0x31, 0x32, 0x12, 0x04, 0x68,
+    0x6f, 0x6d, 0x65, 0x1a, 0x18, 0x0a, 0x0e, 0x2b, 0x31, 0x38, 0x30, 0x30, 0x2d, 0x38, 0x36, 0x37,
+    0x2d, 0x35, 0x33, 0x30, 0x38, 0x12, 0x06, 0x6d, 0x6f, 0x62, 0x69, 0x6c, 0x65,
+];
 
-#[derive(Debug)]
-/// A field, containing the field number and its value.
-struct Field<'a> {
-    field_num: u64,
-    value: FieldValue<'a>,
+/// A PhoneNumber message, borrowing its strings from the input.
+#[derive(Debug, Default, PartialEq)]
+struct PhoneNumberView<'a> {
+    number: Option<&'a str>,
+    phone_type: Option<&'a str>,
+    unknown_fields: UnknownFields<'a>,
 }
 
-impl TryFrom<u64> for WireType {
-    type Error = Error;
-
-    fn try_from(value: u64) -> Result<WireType, Error> {
-        Ok(match value {
-            0 => WireType::Varint,
-            //1 => WireType::I64,  -- not needed for this exercise
-            2 => WireType::Len,
-            5 => WireType::I32,
-            _ => return Err(Error::InvalidWireType),
-        })
+impl<'a> Message<'a> for PhoneNumberView<'a> {
+    fn decode(data: &'a [u8]) -> Result<Self, Error> {
+        let mut phone = PhoneNumberView::default();
+        parse_message(data, |field| {
+            match field.field_num {
+                1 => phone.number = Some(field.value.as_string()?),
+                2 => phone.phone_type = Some(field.value.as_string()?),
+                _ => phone.unknown_fields.push(field),
+            }
+            Ok(())
+        })?;
+        Ok(phone)
     }
 }
 
-impl<'a> FieldValue<'a> {
-    fn as_string(&self) -> Result<&'a str, Error> {
-        let FieldValue::Len(data) = self else {
-            return Err(Error::UnexpectedWireType);
-        };
-        std::str::from_utf8(data).map_err(|_| Error::InvalidString)
-    }
-
-    fn as_bytes(&self) -> Result<&'a [u8], Error> {
-        let FieldValue::Len(data) = self else {
-            return Err(Error::UnexpectedWireType);
-        };
-        Ok(data)
+impl PhoneNumberView<'_> {
+    /// Write the phone number back, with its unknown fields unchanged.
+    fn write_to<W: Write>(&self, writer: &mut MessageWriter<W>) -> Result<(), Error> {
+        if let Some(number) = self.number {
+            writer.write_string(1, number)?;
+        }
+        if let Some(phone_type) = self.phone_type {
+            writer.write_string(2, phone_type)?;
+        }
+        writer.write_unknown_fields(&self.unknown_fields)
     }
+}
 
-    fn as_u64(&self) -> Result<u64, Error> {
-        let FieldValue::Varint(value) = self else {
-            return Err(Error::UnexpectedWireType);
-        };
-        Ok(*value)
-    }
+/// A Person message. The phone numbers are only decoded when `phones` is
+/// iterated.
+struct PersonView<'a> {
+    name: Option<&'a str>,
+    id: Option<u64>,
+    phones: RepeatedIter<'a, PhoneNumberView<'a>>,
+    unknown_fields: UnknownFields<'a>,
 }
 
-/// Parse a VARINT, returning the parsed value and the remaining bytes.
-fn parse_varint(data: &[u8]) -> Result<(u64, &[u8]), Error> {
-    for i in 0..7 {
-        let Some(b) = data.get(i) else {
-            return Err(Error::InvalidVarint);
+impl<'a> Message<'a> for PersonView<'a> {
+    fn decode(data: &'a [u8]) -> Result<Self, Error> {
+        let mut person = PersonView {
+            name: None,
+            id: None,
+            phones: RepeatedIter::new(data, 3),
+            unknown_fields: UnknownFields::default(),
         };
-        if b & 0x80 == 0 {
-            // This is the last byte of the VARINT, so convert it to
-            // a u64 and return it.
-            let mut value = 0u64;
-            for b in data[..=i].iter().rev() {
-                value = (value << 7) | (b & 0x7f) as u64;
+        parse_message(data, |field| {
+            match field.field_num {
+                1 => person.name = Some(field.value.as_string()?),
+                2 => person.id = Some(field.value.as_u64()?),
+                3 => {} // phones are decoded lazily
+                _ => person.unknown_fields.push(field),
             }
-            return Ok((value, &data[i + 1..]));
-        }
+            Ok(())
+        })?;
+        Ok(person)
     }
-
-    // More than 7 bytes is invalid.
-    Err(Error::InvalidVarint)
 }
 
-/// Convert a tag into a field number and a WireType.
-fn unpack_tag(tag: u64) -> Result<(u64, WireType), Error> {
-    let field_num = tag >> 3;
-    let wire_type = WireType::try_from(tag & 0x7)?;
-    Ok((field_num, wire_type))
-}
-
-/// Parse a field, returning the remaining bytes
-fn parse_field(data: &[u8]) -> Result<(Field, &[u8]), Error> {
-    // 1. Read and unpack the tag.
-    let (tag, remainder) = parse_varint(data)?;
-    let (field_num, wire_type) = unpack_tag(tag)?;
-
-    // 2. Based on the wire type, build a Field, consuming as many bytes as
-    //    necessary.
-    let (fieldvalue, remainder) = match wire_type {
-        WireType::Varint => {
-            let (value, remainder) = parse_varint(remainder)?;
-            (FieldValue::Varint(value), remainder)
+impl PersonView<'_> {
+    /// Write the person back, decoding the phone numbers to do so, with the
+    /// unknown fields of every message unchanged.
+    fn write_to<W: Write>(&self, writer: &mut MessageWriter<W>) -> Result<(), Error> {
+        if let Some(name) = self.name {
+            writer.write_string(1, name)?;
         }
-
-        WireType::Len => {
-            let (len, remainder) = parse_varint(remainder)?;
-            let len: usize = len.try_into()?;
-            if remainder.len() < len {
-                return Err(Error::UnexpectedEOF);
-            }
-            let (value, remainder) = remainder.split_at(len);
-            (FieldValue::Len(value), remainder)
+        if let Some(id) = self.id {
+            writer.write_varint(2, id)?;
         }
-
-        WireType::I32 => {
-            if remainder.len() < 4usize {
-                return Err(Error::UnexpectedEOF);
-            }
-            let (value, remainder) = remainder.split_at(4);
-            // Unwrap error because `value` is definitely 4 bytes long.
-            let value = i32::from_le_bytes(value.try_into().unwrap());
-            (FieldValue::I32(value), remainder)
+        for phone in self.phones.clone() {
+            let phone = phone?;
+            writer.write_message(3, |nested| phone.write_to(nested))?;
         }
-    };
-
-    // 3. Return the field, and any un-consumed bytes.
-    Ok((
-        Field {
-            field_num,
-            value: fieldvalue,
-        },
-        remainder,
-    ))
-}
-
-/// Parse a message in the given data, calling `field_callback` for each field in the message.
-///
-/// The entire input is consumed.
-fn parse_message(
-    mut data: &[u8],
-    field_callback: impl Fn(Field) -> Result<(), Error>,
-) -> Result<(), Error> {
-    while !data.is_empty() {
-        let parsed = parse_field(data)?;
-        field_callback(parsed.0)?;
-        data = parsed.1;
+        writer.write_unknown_fields(&self.unknown_fields)
     }
-    Ok(())
 }
 
 fn main() {
-    /// Handle a field in a Person message.
-    fn person_field(field: Field) -> Result<(), Error> {
+    /// Handle a field in a Person message, keeping the fields it doesn't
+    /// know in `unknown`.
+    fn person_field<'a>(field: Field<'a>, unknown: &mut UnknownFields<'a>) -> Result<(), Error> {
         match field.field_num {
             1 => println!("name: {}", field.value.as_string()?),
             2 => println!("id: {}", field.value.as_u64()?),
             3 => {
                 println!("phone:");
-                parse_message(field.value.as_bytes()?, phone_number_field)?;
+                let mut unknown = UnknownFields::default();
+                parse_message(field.value.as_bytes()?, |field| {
+                    phone_number_field(field, &mut unknown)
+                })?;
+                print_unknown_fields("  ", &unknown);
             }
-            _ => {} // skip everything else
+            _ => unknown.push(field),
         }
         Ok(())
     }
 
-    /// Handle a field in a PhoneNumber message.
-    fn phone_number_field(field: Field) -> Result<(), Error> {
+    /// Handle a field in a PhoneNumber message, keeping the fields it
+    /// doesn't know in `unknown`.
+    fn phone_number_field<'a>(
+        field: Field<'a>,
+        unknown: &mut UnknownFields<'a>,
+    ) -> Result<(), Error> {
         match field.field_num {
             1 => println!("  number: {}", field.value.as_string()?),
             2 => println!("  type: {}", field.value.as_string()?),
-            _ => {} // skip everything else
+            _ => unknown.push(field),
         }
         Ok(())
     }
 
-    parse_message(
-        &[
-            0x0a, 0x07, 0x6d, 0x61, 0x78, 0x77, 0x65, 0x6c, 0x6c, 0x10, 0x2a, 0x1a, 0x16, 0x0a,
-            0x0e, 0x2b, 0x31, 0x32, 0x30, 0x32, 0x2d, 0x35, 0x35, 0x35, 0x2d, 0x31, 0x32, 0x31,
-            0x32, 0x12, 0x04, 0x68, 0x6f, 0x6d, 0x65, 0x1a, 0x18, 0x0a, 0x0e, 0x2b, 0x31, 0x38,
-            0x30, 0x30, 0x2d, 0x38, 0x36, 0x37, 0x2d, 0x35, 0x33, 0x30, 0x38, 0x12, 0x06, 0x6d,
-            0x6f, 0x62, 0x69, 0x6c, 0x65,
-        ],
-        person_field,
-    )
-    .unwrap()
+    fn print_unknown_fields(indent: &str, unknown: &UnknownFields) {
+        for field in unknown.iter() {
+            println!(
+                "{indent}unknown field {} ({:?}): {:02x?}",
+                field.field_num, field.wire_type, field.raw
+            );
+        }
+    }
+
+    let mut unknown = UnknownFields::default();
+    parse_message(PERSON, |field| person_field(field, &mut unknown)).unwrap();
+    print_unknown_fields("", &unknown);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn person_view() {
+        let person = PersonView::decode(PERSON).unwrap();
+        assert_eq!(person.name, Some("maxwell"));
+        assert_eq!(person.id, Some(42));
+
+        let phones: Vec<_> = person.phones.collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            phones,
+            vec![
+                PhoneNumberView {
+                    number: Some("+1202-555-1212"),
+                    phone_type: Some("home"),
+                    unknown_fields: UnknownFields::default(),
+                },
+                PhoneNumberView {
+                    number: Some("+1800-867-5308"),
+                    phone_type: Some("mobile"),
+                    unknown_fields: UnknownFields::default(),
+                },
+            ]
+        );
+
+        // The views borrow from the input rather than copying it.
+        let name = person.name.unwrap();
+        assert!(PERSON.as_ptr_range().contains(&name.as_ptr()));
+    }
+
+    #[test]
+    fn lazy_phones() {
+        // name: "ab", phones: [{number: "1"}, <a varint where a string belongs>]
+        let data = [
+            0x0a, 0x02, b'a', b'b', 0x1a, 0x03, 0x0a, 0x01, b'1', 0x1a, 0x02, 0x08, 0x01,
+        ];
+        let person = PersonView::decode(&data).unwrap();
+        assert_eq!(person.name, Some("ab"));
+        assert_eq!(person.id, None);
+
+        let mut phones = person.phones.clone();
+        assert_eq!(phones.next().unwrap().unwrap().number, Some("1"));
+        let error = phones.next().unwrap().unwrap_err();
+        assert!(matches!(error.kind(), Error::UnexpectedWireType));
+        assert_eq!(error.offset(), Some(11));
+        assert_eq!(error.path().unwrap().to_string(), "3[1].1");
+        assert!(phones.next().is_none());
+
+        // A message that is not a Len field is an error too.
+        let mut phones = RepeatedIter::<PhoneNumberView>::new(&[0x18, 0x01], 3);
+        assert!(matches!(
+            phones.next().unwrap().unwrap_err().kind(),
+            Error::UnexpectedWireType
+        ));
+    }
+
+    #[test]
+    fn unknown_round_trip() {
+        // Written by a newer schema, which added fields to both messages.
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_string(1, "maxwell").unwrap();
+        writer.write_varint(2, 42).unwrap();
+        writer
+            .write_message(3, |phone| {
+                phone.write_string(1, "+1202-555-1212")?;
+                phone.write_string(2, "home")?;
+                phone.write_varint(3, 1)
+            })
+            .unwrap();
+        writer
+            .write_message(3, |phone| phone.write_string(1, "+1800-867-5308"))
+            .unwrap();
+        writer.write_double(4, 1.25).unwrap();
+        writer
+            .write_message(5, |address| address.write_string(1, "Main St"))
+            .unwrap();
+        let data = writer.into_inner();
+
+        let person = PersonView::decode(&data).unwrap();
+        let unknown: Vec<u64> = person.unknown_fields.iter().map(|f| f.field_num).collect();
+        assert_eq!(unknown, [4, 5]);
+        let phone = person.phones.clone().next().unwrap().unwrap();
+        assert_eq!(phone.unknown_fields.len(), 1);
+
+        let mut writer = MessageWriter::new(Vec::new());
+        person.write_to(&mut writer).unwrap();
+        assert_eq!(writer.into_inner(), data);
+
+        // Messages without unknown fields come back unchanged too.
+        let mut writer = MessageWriter::new(Vec::new());
+        PersonView::decode(PERSON)
+            .unwrap()
+            .write_to(&mut writer)
+            .unwrap();
+        assert_eq!(writer.into_inner(), PERSON);
+    }
 }
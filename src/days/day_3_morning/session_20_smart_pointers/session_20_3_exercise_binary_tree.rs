@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
 
 /// # 20.3 Exercise: Binary Tree
 ///
@@ -12,74 +13,877 @@ use std::cmp::Ordering;
 /// order.
 ///
 
-impl<'a, T: Ord + Copy> Iterator for BTIter<'a, T> {
-    type Item = T;
+/// An in-order iterator over references to the entries of a map.
+///
+/// Each end keeps the path of nodes whose entries it hasn't yielded yet, so
+/// no recursion is needed. The ends are done once one of them reaches the
+/// node last yielded by the other, which for a range starts out as the node
+/// just outside the other end of the range.
+pub struct Iter<'a, K, V> {
+    front: Vec<&'a BinaryTreeNode<K, V>>,
+    back: Vec<&'a BinaryTreeNode<K, V>>,
+    front_last: Option<&'a BinaryTreeNode<K, V>>,
+    back_last: Option<&'a BinaryTreeNode<K, V>>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn new(map: &'a BinaryTreeMap<K, V>) -> Self {
+        let mut iter = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            front_last: None,
+            back_last: None,
+        };
+        iter.push_left(&map.root);
+        iter.push_right(&map.root);
+        iter
+    }
+
+    /// An iterator over the entries of `map` with keys within `range`.
+    fn range(map: &'a BinaryTreeMap<K, V>, range: &impl RangeBounds<K>) -> Self
+    where
+        K: Ord,
+    {
+        let after_start = |key: &K| match range.start_bound() {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &K| match range.end_bound() {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        let mut iter = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            front_last: None,
+            back_last: None,
+        };
+        // Find the paths to the first and last keys in the range, and the
+        // closest nodes outside of it.
+        let mut subtree = &map.root;
+        while let Some(node) = &subtree.0 {
+            if after_start(&node.key) {
+                iter.front.push(node);
+                subtree = &node.left;
+            } else {
+                iter.front_last = Some(node);
+                subtree = &node.right;
+            }
+        }
+        let mut subtree = &map.root;
+        while let Some(node) = &subtree.0 {
+            if before_end(&node.key) {
+                iter.back.push(node);
+                subtree = &node.right;
+            } else {
+                iter.back_last = Some(node);
+                subtree = &node.left;
+            }
+        }
+        iter
+    }
+
+    /// Push `tree` and its left descendants, smallest last.
+    fn push_left(&mut self, mut tree: &'a Subtree<K, V>) {
+        while let Some(node) = &tree.0 {
+            self.front.push(node);
+            tree = &node.left;
+        }
+    }
+
+    /// Push `tree` and its right descendants, largest last.
+    fn push_right(&mut self, mut tree: &'a Subtree<K, V>) {
+        while let Some(node) = &tree.0 {
+            self.back.push(node);
+            tree = &node.right;
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        None
+        let node = self.front.pop()?;
+        if self.back_last.is_some_and(|last| std::ptr::eq(last, node)) {
+            self.front.clear();
+            self.back.clear();
+            return None;
+        }
+        self.push_left(&node.right);
+        self.front_last = Some(node);
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back.pop()?;
+        if self.front_last.is_some_and(|last| std::ptr::eq(last, node)) {
+            self.front.clear();
+            self.back.clear();
+            return None;
+        }
+        self.push_right(&node.left);
+        self.back_last = Some(node);
+        Some((&node.key, &node.value))
+    }
+}
+
+/// An in-order iterator over the entries of a map, with mutable references
+/// to the values.
+///
+/// Each pending node is kept split into its key, its value and its right
+/// subtree, so that the references handed out never overlap.
+pub struct IterMut<'a, K, V>(Vec<(&'a K, &'a mut V, &'a mut Subtree<K, V>)>);
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    /// Push `tree` and its left descendants, smallest last.
+    fn push_left(&mut self, mut tree: &'a mut Subtree<K, V>) {
+        while let Some(node) = &mut tree.0 {
+            let BinaryTreeNode {
+                key,
+                value,
+                left,
+                right,
+                ..
+            } = &mut **node;
+            self.0.push((key, value, right));
+            tree = left;
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value, right) = self.0.pop()?;
+        self.push_left(right);
+        Some((key, value))
+    }
+}
+
+/// An in-order iterator taking the entries out of a map.
+pub struct IntoIter<K, V>(Vec<TreeNode<K, V>>);
+
+impl<K, V> IntoIter<K, V> {
+    /// Push `tree` and its left descendants, detached from each other.
+    fn push_left(&mut self, mut tree: Subtree<K, V>) {
+        while let Some(mut node) = tree.0 {
+            tree = std::mem::replace(&mut node.left, Subtree(None));
+            self.0.push(node);
+        }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let node = self.0.pop()?;
+        let BinaryTreeNode {
+            key, value, right, ..
+        } = *node;
+        self.push_left(right);
+        Some((key, value))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BinaryTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        Iter::new(self)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut BinaryTreeMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        let mut iter = IterMut(Vec::new());
+        iter.push_left(&mut self.root);
+        iter
+    }
+}
+
+impl<K, V> IntoIterator for BinaryTreeMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let mut iter = IntoIter(Vec::new());
+        iter.push_left(self.root);
+        iter
+    }
+}
+
+/// An in-order iterator over references to the values of a set.
+pub struct BTIter<'a, T>(Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for BTIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|(value, _)| value)
+    }
+}
+
+impl<T> DoubleEndedIterator for BTIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(value, _)| value)
+    }
+}
+
+/// An in-order iterator taking the values out of a set.
+pub struct SetIntoIter<T>(IntoIter<T, ()>);
+
+impl<T> Iterator for SetIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|(value, _)| value)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinaryTree<T> {
+    type Item = &'a T;
+    type IntoIter = BTIter<'a, T>;
+
+    fn into_iter(self) -> BTIter<'a, T> {
+        BTIter(Iter::new(&self.map))
+    }
+}
+
+impl<T> IntoIterator for BinaryTree<T> {
+    type Item = T;
+    type IntoIter = SetIntoIter<T>;
+
+    fn into_iter(self) -> SetIntoIter<T> {
+        SetIntoIter(self.map.into_iter())
+    }
+}
+
+/// A view into a single entry of a map, which may be vacant or occupied,
+/// as returned by [`BinaryTreeMap::entry`].
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry whose key is in the map.
+pub struct OccupiedEntry<'a, K, V> {
+    node: &'a mut BinaryTreeNode<K, V>,
+}
+
+/// An entry whose key isn't in the map yet.
+pub struct VacantEntry<'a, K, V> {
+    map: &'a mut BinaryTreeMap<K, V>,
+    key: K,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// The value, after inserting `default` if the entry is vacant.
+    fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// The value, after inserting the result of `default` if the entry is
+    /// vacant.
+    fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Apply `f` to the value if the entry is occupied.
+    fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    fn key(&self) -> &K {
+        &self.node.key
+    }
+
+    fn get(&self) -> &V {
+        &self.node.value
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        &mut self.node.value
+    }
+
+    /// The value, borrowed for as long as the map was.
+    fn into_mut(self) -> &'a mut V {
+        &mut self.node.value
+    }
+
+    /// Replace the value, returning the old one.
+    fn insert(&mut self, value: V) -> V {
+        std::mem::replace(&mut self.node.value, value)
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Insert `value` under the key of the entry, returning a reference to
+    /// it.
+    fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        let Insertion::New(path) = map.root.insert(self.key, value) else {
+            unreachable!("the key of a vacant entry is in the map");
+        };
+        map.len += 1;
+        &mut map.root.descend(path).value
     }
 }
-struct BTIter<'a, T>(&'a BinaryTree<T>);
 
 #[derive(Debug)]
-struct BinaryTreeNode<T> {
-    value: T,
-    left: BinaryTree<T>,
-    right: BinaryTree<T>,
+struct BinaryTreeNode<K, V> {
+    key: K,
+    value: V,
+    /// The number of nodes on the longest path down from this one,
+    /// including itself.
+    height: usize,
+    left: Subtree<K, V>,
+    right: Subtree<K, V>,
+}
+
+type TreeNode<K, V> = Box<BinaryTreeNode<K, V>>;
+
+#[derive(Debug)]
+struct Subtree<K, V>(Option<TreeNode<K, V>>);
+
+/// Which child of a node to go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn other(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+/// The outcome of inserting into a subtree.
+enum Insertion<V> {
+    /// The key was added, at the end of this path from the root of the
+    /// subtree. The path is reversed, so that its first step is last.
+    New(Vec<Side>),
+    /// The key was present already, with this value.
+    Replaced(V),
+}
+
+/// A map from keys to values, ordered by key and stored in a binary tree.
+///
+/// The tree is kept balanced as an AVL tree: the heights of the two subtrees
+/// of any node differ by at most one, so the height of the tree is
+/// logarithmic in its length whatever order keys are inserted in.
+#[derive(Debug)]
+pub struct BinaryTreeMap<K, V> {
+    root: Subtree<K, V>,
+    len: usize,
 }
 
 /// A container storing a set of values, using a binary tree.
 ///
-/// If the same value is added multiple times, it is only stored once.
+/// If the same value is added multiple times, it is only stored once. This
+/// is a [`BinaryTreeMap`] with the values as keys, mapped to nothing.
+#[derive(Debug)]
+pub struct BinaryTree<T> {
+    map: BinaryTreeMap<T, ()>,
+}
 
-type TreeNode<T> = Box<BinaryTreeNode<T>>;
+impl<K: Ord, V> BinaryTreeMap<K, V> {
+    fn new() -> Self {
+        Self {
+            root: Subtree(None),
+            len: 0,
+        }
+    }
 
-#[derive(Debug)]
-pub struct BinaryTree<T>(Option<TreeNode<T>>);
+    fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(self)
+    }
+
+    fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        self.into_iter()
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        let mut tree = &self.root;
+        while let Some(node) = &tree.0 {
+            tree = match node.key.cmp(key) {
+                Ordering::Less => &node.right,
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => &node.left,
+            };
+        }
+        None
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut tree = &mut self.root;
+        loop {
+            let node = tree.0.as_mut()?;
+            tree = match node.key.cmp(key) {
+                Ordering::Less => &mut node.right,
+                Ordering::Equal => return Some(&mut node.value),
+                Ordering::Greater => &mut node.left,
+            };
+        }
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert `value` under `key`, returning the value it replaced, if any.
+    /// The key already in the map is kept.
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.root.insert(key, value) {
+            Insertion::New(_) => {
+                self.len += 1;
+                None
+            }
+            Insertion::Replaced(old) => Some(old),
+        }
+    }
+
+    /// The entry for `key`, to inspect or update in place, like
+    /// `HashMap::entry`.
+    fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        // Looking the key up twice keeps the borrow checker happy: a borrow
+        // returned from one branch of a `match` would last into the other.
+        if !self.contains_key(&key) {
+            return Entry::Vacant(VacantEntry { map: self, key });
+        }
+        let mut tree = &mut self.root;
+        loop {
+            let node = tree.0.as_mut().unwrap();
+            tree = match node.key.cmp(&key) {
+                Ordering::Less => &mut node.right,
+                Ordering::Equal => return Entry::Occupied(OccupiedEntry { node }),
+                Ordering::Greater => &mut node.left,
+            };
+        }
+    }
+
+    /// Remove `key`, returning its value if it was present.
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let (_, value) = self.root.remove(key)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Remove and return the entry with the smallest key.
+    fn pop_min(&mut self) -> Option<(K, V)> {
+        let entry = self.root.pop_min()?;
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// Remove and return the entry with the largest key.
+    fn pop_max(&mut self) -> Option<(K, V)> {
+        let entry = self.root.pop_max()?;
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// The entry with the smallest key.
+    fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// The entry with the largest key.
+    fn last(&self) -> Option<(&K, &V)> {
+        self.iter().next_back()
+    }
+
+    /// An iterator over the entries with keys within `range`, in order,
+    /// such as `map.range(3..7)` or `map.range(..=x)`.
+    ///
+    /// Panics if the range starts after it ends, like `BTreeMap::range`.
+    fn range(&self, range: impl RangeBounds<K>) -> Iter<'_, K, V> {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+                panic!("range start and end are equal and excluded")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if start > end => panic!("range start is greater than range end"),
+            _ => {}
+        }
+        Iter::range(self, &range)
+    }
+
+    /// The entry with the largest key less than or equal to `key`.
+    fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.below(key, true)
+    }
+
+    /// The entry with the smallest key greater than or equal to `key`.
+    fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.above(key, true)
+    }
+
+    /// The entry with the largest key less than `key`.
+    fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.below(key, false)
+    }
+
+    /// The entry with the smallest key greater than `key`.
+    fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.above(key, false)
+    }
+
+    /// The entry with the largest key less than `key`, or equal to it if
+    /// `inclusive`.
+    fn below(&self, key: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut tree = &self.root;
+        let mut best = None;
+        while let Some(node) = &tree.0 {
+            match node.key.cmp(key) {
+                Ordering::Equal if inclusive => return Some((&node.key, &node.value)),
+                Ordering::Less => {
+                    best = Some((&node.key, &node.value));
+                    tree = &node.right;
+                }
+                _ => tree = &node.left,
+            }
+        }
+        best
+    }
+
+    /// The entry with the smallest key greater than `key`, or equal to it if
+    /// `inclusive`.
+    fn above(&self, key: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut tree = &self.root;
+        let mut best = None;
+        while let Some(node) = &tree.0 {
+            match node.key.cmp(key) {
+                Ordering::Equal if inclusive => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    best = Some((&node.key, &node.value));
+                    tree = &node.left;
+                }
+                _ => tree = &node.right,
+            }
+        }
+        best
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The number of nodes on the longest path from the root to a leaf.
+    fn height(&self) -> usize {
+        self.root.height()
+    }
+}
 
 // Implement `new`, `insert`, and `has`.
 
-impl<T: Ord + Copy> BinaryTree<T> {
+impl<T: Ord> BinaryTree<T> {
     fn new() -> Self {
-        Self(None)
+        Self {
+            map: BinaryTreeMap::new(),
+        }
     }
 
-    fn iter(&self) -> BTIter<T> {
-        BTIter(self)
+    fn iter(&self) -> BTIter<'_, T> {
+        BTIter(Iter::new(&self.map))
     }
 
-    fn has(&self, value: T) -> bool {
-        match &self.0 {
-            Some(node) => match node.value.cmp(&value) {
-                Ordering::Less => node.right.has(value),
-                Ordering::Equal => true,
-                Ordering::Greater => node.left.has(value),
-            },
-            _ => false,
-        }
+    fn has(&self, value: &T) -> bool {
+        self.map.contains_key(value)
     }
 
     fn insert(&mut self, value: T) {
-        match &mut self.0 {
-            Some(node) => match node.value.cmp(&value) {
-                Ordering::Less => node.right.insert(value),
-                Ordering::Equal => {}
-                Ordering::Greater => node.left.insert(value),
-            },
-            _ => {
-                self.0 = Some(Box::new(BinaryTreeNode {
+        self.map.insert(value, ());
+    }
+
+    /// Remove `value`, returning whether it was present.
+    fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    /// Remove and return the smallest value.
+    fn pop_min(&mut self) -> Option<T> {
+        self.map.pop_min().map(|(value, _)| value)
+    }
+
+    /// Remove and return the largest value.
+    fn pop_max(&mut self) -> Option<T> {
+        self.map.pop_max().map(|(value, _)| value)
+    }
+
+    /// The smallest value.
+    fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    /// The largest value.
+    fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    /// An iterator over the values within `range`, in order, such as
+    /// `tree.range(3..7)` or `tree.range(..=x)`.
+    ///
+    /// Panics if the range starts after it ends, like `BTreeSet::range`.
+    fn range(&self, range: impl RangeBounds<T>) -> BTIter<'_, T> {
+        BTIter(self.map.range(range))
+    }
+
+    /// The largest value less than or equal to `value`.
+    fn floor(&self, value: &T) -> Option<&T> {
+        self.map.floor(value).map(|(value, _)| value)
+    }
+
+    /// The smallest value greater than or equal to `value`.
+    fn ceiling(&self, value: &T) -> Option<&T> {
+        self.map.ceiling(value).map(|(value, _)| value)
+    }
+
+    /// The largest value less than `value`.
+    fn predecessor(&self, value: &T) -> Option<&T> {
+        self.map.predecessor(value).map(|(value, _)| value)
+    }
+
+    /// The smallest value greater than `value`.
+    fn successor(&self, value: &T) -> Option<&T> {
+        self.map.successor(value).map(|(value, _)| value)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// The number of nodes on the longest path from the root to a leaf.
+    fn height(&self) -> usize {
+        self.map.height()
+    }
+}
+
+impl<K: Ord, V> Subtree<K, V> {
+    /// Insert `value` under `key`, or replace the value already there. This
+    /// recurses, but only as deep as the tree is high.
+    fn insert(&mut self, key: K, value: V) -> Insertion<V> {
+        let Some(node) = &mut self.0 else {
+            self.0 = Some(Box::new(BinaryTreeNode {
+                key,
+                value,
+                height: 1,
+                left: Subtree(None),
+                right: Subtree(None),
+            }));
+            return Insertion::New(Vec::new());
+        };
+        let side = match node.key.cmp(&key) {
+            Ordering::Less => Side::Right,
+            Ordering::Equal => {
+                return Insertion::Replaced(std::mem::replace(&mut node.value, value));
+            }
+            Ordering::Greater => Side::Left,
+        };
+        let mut insertion = node.child_mut(side).insert(key, value);
+        if let Insertion::New(path) = &mut insertion {
+            path.push(side);
+            self.rebalance(path);
+        }
+        insertion
+    }
+
+    /// Remove `key` if present, returning its entry.
+    fn remove(&mut self, key: &K) -> Option<(K, V)> {
+        let node = self.0.as_mut()?;
+        let removed = match node.key.cmp(key) {
+            Ordering::Less => node.right.remove(key),
+            Ordering::Greater => node.left.remove(key),
+            Ordering::Equal if node.left.0.is_some() && node.right.0.is_some() => {
+                // Replace the entry by the next one in order.
+                let (key, value) = node.right.pop_min().unwrap();
+                Some((
+                    std::mem::replace(&mut node.key, key),
+                    std::mem::replace(&mut node.value, value),
+                ))
+            }
+            // The only child, if any, takes the place of the node.
+            Ordering::Equal => {
+                let node = self.0.take().unwrap();
+                let BinaryTreeNode {
+                    key,
                     value,
-                    left: BinaryTree::new(),
-                    right: BinaryTree::new(),
-                }));
+                    left,
+                    right,
+                    ..
+                } = *node;
+                *self = if left.0.is_some() { left } else { right };
+                return Some((key, value));
             }
+        };
+        if removed.is_some() {
+            self.rebalance(&mut Vec::new());
         }
+        removed
     }
+}
 
-    fn len(&self) -> usize {
-        match &self.0 {
-            Some(node) => node.left.len() + node.right.len() + 1,
-            _ => 0,
+impl<K, V> Subtree<K, V> {
+    fn height(&self) -> usize {
+        self.0.as_ref().map_or(0, |node| node.height)
+    }
+
+    /// The node at the end of `path`, which is reversed like the paths of
+    /// [`Insertion::New`].
+    fn descend(&mut self, mut path: Vec<Side>) -> &mut BinaryTreeNode<K, V> {
+        let mut node = self.0.as_mut().unwrap();
+        while let Some(side) = path.pop() {
+            node = node.child_mut(side).0.as_mut().unwrap();
+        }
+        node
+    }
+
+    /// Remove and return the entry with the smallest key.
+    fn pop_min(&mut self) -> Option<(K, V)> {
+        let node = self.0.as_mut()?;
+        if node.left.0.is_some() {
+            let entry = node.left.pop_min();
+            self.rebalance(&mut Vec::new());
+            return entry;
+        }
+        let node = self.0.take().unwrap();
+        let BinaryTreeNode {
+            key, value, right, ..
+        } = *node;
+        *self = right;
+        Some((key, value))
+    }
+
+    /// Remove and return the entry with the largest key.
+    fn pop_max(&mut self) -> Option<(K, V)> {
+        let node = self.0.as_mut()?;
+        if node.right.0.is_some() {
+            let entry = node.right.pop_max();
+            self.rebalance(&mut Vec::new());
+            return entry;
+        }
+        let node = self.0.take().unwrap();
+        let BinaryTreeNode {
+            key, value, left, ..
+        } = *node;
+        *self = left;
+        Some((key, value))
+    }
+
+    /// How much higher the right subtree is than the left one.
+    fn balance(&self) -> isize {
+        self.0.as_ref().map_or(0, |node| {
+            node.right.height() as isize - node.left.height() as isize
+        })
+    }
+
+    /// Restore the balance of the root after one of its subtrees grew or
+    /// shrank by one level, keeping `path` leading to the same node.
+    fn rebalance(&mut self, path: &mut Vec<Side>) {
+        let Some(node) = &mut self.0 else {
+            return;
+        };
+        node.update_height();
+        let balance = self.balance();
+        let heavy = match balance {
+            2 => Side::Right,
+            -2 => Side::Left,
+            _ => return,
+        };
+        // A child leaning towards the middle needs a rotation of its own
+        // first, which only moves nodes below it.
+        let child = self.0.as_mut().unwrap().child_mut(heavy);
+        if child.balance() * balance < 0 {
+            if path.last() == Some(&heavy) {
+                path.pop();
+                child.rotate(heavy.other(), path);
+                path.push(heavy);
+            } else {
+                child.rotate(heavy.other(), &mut Vec::new());
+            }
+        }
+        self.rotate(heavy, path);
+    }
+
+    /// Make the child on side `up` the root, with the old root as its child
+    /// on the other side, keeping `path` leading to the same node.
+    fn rotate(&mut self, up: Side, path: &mut Vec<Side>) {
+        let down = up.other();
+        let mut node = self.0.take().unwrap();
+        let mut pivot = node.child_mut(up).0.take().unwrap();
+        *node.child_mut(up) = std::mem::replace(pivot.child_mut(down), Subtree(None));
+        node.update_height();
+        *pivot.child_mut(down) = Subtree(Some(node));
+        pivot.update_height();
+        self.0 = Some(pivot);
+
+        // Paths through the pivot get one step shorter, and paths through
+        // the old root one step longer. The subtree moving across goes from
+        // the pivot's `down` side to the old root's `up` side.
+        match path.pop() {
+            None => path.push(down),
+            Some(side) if side == down => path.extend([down, down]),
+            Some(_) => {
+                if path.last() == Some(&down) {
+                    *path.last_mut().unwrap() = up;
+                    path.push(down);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V> BinaryTreeNode<K, V> {
+    fn update_height(&mut self) {
+        self.height = 1 + self.left.height().max(self.right.height());
+    }
+
+    fn child_mut(&mut self, side: Side) -> &mut Subtree<K, V> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
         }
     }
 }
@@ -104,7 +908,7 @@ mod test {
     fn has() {
         let mut tree = BinaryTree::new();
         fn check_has(tree: &BinaryTree<i32>, exp: &[bool]) {
-            let got: Vec<bool> = (0..exp.len()).map(|i| tree.has(i as i32)).collect();
+            let got: Vec<bool> = (0..exp.len()).map(|i| tree.has(&(i as i32))).collect();
             assert_eq!(&got, exp);
         }
 
@@ -126,7 +930,335 @@ mod test {
             tree.insert(i);
         }
         assert_eq!(tree.len(), 100);
-        assert!(tree.has(50));
+        assert!(tree.has(&50));
+        assert_eq!(tree.height(), 7);
+    }
+
+    /// Check the ordering, heights and balance of every node.
+    fn check_avl<K: Ord, V>(tree: &Subtree<K, V>, bounds: (Option<&K>, Option<&K>)) -> usize {
+        let Some(node) = &tree.0 else {
+            return 0;
+        };
+        assert!(bounds.0.is_none_or(|low| low < &node.key));
+        assert!(bounds.1.is_none_or(|high| &node.key < high));
+        let left = check_avl(&node.left, (bounds.0, Some(&node.key)));
+        let right = check_avl(&node.right, (Some(&node.key), bounds.1));
+        assert!(left.abs_diff(right) <= 1);
+        assert_eq!(node.height, 1 + left.max(right));
+        node.height
+    }
+
+    #[test]
+    fn balanced() {
+        let n = 1000;
+        let zigzag = (0..n / 2).flat_map(|i| [i, n - 1 - i]);
+        let orders: [Vec<i32>; 4] = [
+            (0..n).collect(),
+            (0..n).rev().collect(),
+            zigzag.collect(),
+            (0..n).map(|i| i * 7919 % n).collect(),
+        ];
+        for order in orders {
+            let mut tree = BinaryTree::new();
+            for (i, &value) in order.iter().enumerate() {
+                tree.insert(value);
+                if i % 97 == 0 {
+                    check_avl(&tree.map.root, (None, None));
+                }
+            }
+            check_avl(&tree.map.root, (None, None));
+            assert_eq!(tree.len(), n as usize);
+            // An AVL tree of 1000 nodes is at most 14 high.
+            assert!(tree.height() <= 14, "height {}", tree.height());
+            assert!(tree.iter().copied().eq(0..n));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let n = 500;
+        let mut tree = BinaryTree::new();
+        for i in 0..n {
+            tree.insert(i * 7 % n);
+        }
+        assert!(!tree.remove(&n));
+        for (i, value) in (0..n).map(|i| i * 13 % n).enumerate() {
+            assert!(tree.remove(&value), "{value}");
+            assert!(!tree.remove(&value));
+            assert!(!tree.has(&value));
+            assert_eq!(tree.len(), n - 1 - i);
+            if i % 37 == 0 {
+                check_avl(&tree.map.root, (None, None));
+            }
+        }
+        assert_eq!(tree.height(), 0);
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn min_max() {
+        let mut tree = BinaryTree::new();
+        assert_eq!((tree.first(), tree.last()), (None, None));
+        assert_eq!((tree.pop_min(), tree.pop_max()), (None, None));
+        for value in [5, 2, 8, 1, 9, 3, 7] {
+            tree.insert(value);
+        }
+        assert_eq!((tree.first(), tree.last()), (Some(&1), Some(&9)));
+        assert_eq!(tree.pop_min(), Some(1));
+        assert_eq!(tree.pop_max(), Some(9));
+        assert_eq!(tree.pop_max(), Some(8));
+        assert_eq!((tree.first(), tree.last()), (Some(&2), Some(&7)));
+        assert_eq!(tree.len(), 4);
+        check_avl(&tree.map.root, (None, None));
+
+        let mut sorted = Vec::new();
+        let mut tree: BinaryTree<i32> = (0..300).rev().fold(BinaryTree::new(), |mut tree, i| {
+            tree.insert(i);
+            tree
+        });
+        while let Some(value) = tree.pop_min() {
+            sorted.push(value);
+            check_avl(&tree.map.root, (None, None));
+        }
+        assert!(sorted.into_iter().eq(0..300));
+        assert_eq!(tree.len(), 0);
+    }
+
+    #[test]
+    fn range() {
+        let mut tree = BinaryTree::new();
+        for value in (0..100).step_by(10) {
+            tree.insert(value);
+        }
+        fn range<'a>(iter: impl Iterator<Item = &'a i32>) -> Vec<i32> {
+            iter.copied().collect()
+        }
+        assert_eq!(range(tree.range(20..50)), [20, 30, 40]);
+        assert_eq!(range(tree.range(15..=50)), [20, 30, 40, 50]);
+        assert_eq!(range(tree.range(..25)), [0, 10, 20]);
+        assert_eq!(range(tree.range(85..)), [90]);
+        assert_eq!(range(tree.range(..)), range(tree.iter()));
+        assert_eq!(range(tree.range(20..50).rev()), [40, 30, 20]);
+        assert_eq!(
+            range(tree.range((Bound::Excluded(20), Bound::Excluded(50)))),
+            [30, 40]
+        );
+        for empty in [tree.range(21..29), tree.range(30..30), tree.range(100..)] {
+            assert_eq!(range(empty), []);
+        }
+        assert_eq!(range(tree.range(21..29).rev()), []);
+        assert_eq!(range(tree.range(-10..0)), []);
+
+        let mut iter = tree.range(10..=60);
+        assert_eq!(iter.next(), Some(&10));
+        assert_eq!(iter.next_back(), Some(&60));
+        assert_eq!(range(iter.by_ref()), [20, 30, 40, 50]);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "range start is greater than range end")]
+    fn range_backwards() {
+        let tree: BinaryTree<i32> = BinaryTree::new();
+        let (start, end) = (5, 3);
+        tree.range(start..end);
+    }
+
+    #[test]
+    fn neighbors() {
+        let mut tree = BinaryTree::new();
+        for value in [10, 20, 30] {
+            tree.insert(value);
+        }
+        assert_eq!(tree.floor(&20), Some(&20));
+        assert_eq!(tree.floor(&25), Some(&20));
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.ceiling(&20), Some(&20));
+        assert_eq!(tree.ceiling(&25), Some(&30));
+        assert_eq!(tree.ceiling(&35), None);
+        assert_eq!(tree.successor(&20), Some(&30));
+        assert_eq!(tree.successor(&5), Some(&10));
+        assert_eq!(tree.successor(&30), None);
+        assert_eq!(tree.predecessor(&20), Some(&10));
+        assert_eq!(tree.predecessor(&10), None);
+    }
+
+    #[test]
+    fn million_sorted() {
+        let n = 1_000_000;
+        let mut tree = BinaryTree::new();
+        for i in 0..n {
+            tree.insert(i);
+        }
+        assert_eq!(tree.len(), n as usize);
+        assert!(tree.has(&(n - 1)) && !tree.has(&n));
+        // A perfectly balanced tree would be 20 high.
+        assert!(tree.height() <= 21, "height {}", tree.height());
+        assert_eq!(tree.iter().next_back(), Some(&(n - 1)));
+    }
+
+    #[test]
+    fn iter() {
+        let mut tree = BinaryTree::new();
+        assert_eq!(tree.iter().next(), None);
+        for value in [5, 2, 8, 1, 9, 3, 7, 2] {
+            tree.insert(value);
+        }
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 5, 7, 8, 9]
+        );
+        assert_eq!(
+            tree.iter().rev().copied().collect::<Vec<_>>(),
+            vec![9, 8, 7, 5, 3, 2, 1]
+        );
+        assert_eq!((&tree).into_iter().sum::<i32>(), 35);
+        assert_eq!(
+            tree.into_iter().collect::<Vec<_>>(),
+            vec![1, 2, 3, 5, 7, 8, 9]
+        );
+    }
+
+    #[test]
+    fn iter_both_ends() {
+        let mut tree = BinaryTree::new();
+        for value in [4, 2, 6, 1, 3, 5, 7] {
+            tree.insert(value);
+        }
+        // Alternate ends until they meet, from either side.
+        for first_back in [false, true] {
+            let mut iter = tree.iter();
+            let mut seen = Vec::new();
+            let mut back = first_back;
+            while let Some(&value) = if back { iter.next_back() } else { iter.next() } {
+                seen.push(value);
+                back = !back;
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next_back(), None);
+            seen.sort();
+            assert_eq!(seen, vec![1, 2, 3, 4, 5, 6, 7]);
+        }
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&7));
+        assert_eq!(iter.by_ref().rev().nth(4), Some(&2));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn strings() {
+        let mut tree = BinaryTree::new();
+        for word in ["pear", "apple", "fig", "apple"] {
+            tree.insert(word.to_string());
+        }
+        assert!(tree.has(&"fig".to_string()));
+        assert!(!tree.has(&"kiwi".to_string()));
+        let words: Vec<&String> = tree.iter().collect();
+        assert_eq!(words, ["apple", "fig", "pear"]);
+        let mut owned = Vec::new();
+        for word in tree {
+            owned.push(word);
+        }
+        assert_eq!(owned, ["apple", "fig", "pear"]);
+    }
+
+    #[test]
+    fn map() {
+        let mut map = BinaryTreeMap::new();
+        assert_eq!(map.insert(3, "three"), None);
+        assert_eq!(map.insert(1, "one"), None);
+        assert_eq!(map.insert(3, "drei"), Some("three"));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&3), Some(&"drei"));
+        assert_eq!(map.get(&2), None);
+        *map.get_mut(&1).unwrap() = "eins";
+        assert_eq!(map.get_mut(&2), None);
+        assert_eq!(map.first(), Some((&1, &"eins")));
+        assert_eq!(map.ceiling(&2), Some((&3, &"drei")));
+        assert_eq!(map.remove(&1), Some("eins"));
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.pop_max(), Some((3, "drei")));
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn entry() {
+        let mut counts = BinaryTreeMap::new();
+        for word in "the cat saw the dog and the dog saw the cat".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        let counts: Vec<(&str, i32)> = counts.into_iter().collect();
+        assert_eq!(
+            counts,
+            [("and", 1), ("cat", 2), ("dog", 2), ("saw", 2), ("the", 4)]
+        );
+
+        let mut map = BinaryTreeMap::new();
+        assert_eq!(map.entry(1).key(), &1);
+        map.entry(1).and_modify(|value| *value += 10).or_default();
+        map.entry(1).and_modify(|value| *value += 10).or_default();
+        assert_eq!(map.get(&1), Some(&10));
+        assert_eq!(*map.entry(2).or_insert_with(|| 5), 5);
+        let Entry::Occupied(mut entry) = map.entry(2) else {
+            panic!("2 is in the map");
+        };
+        assert_eq!((entry.key(), entry.get()), (&2, &5));
+        assert_eq!(entry.insert(6), 5);
+        *entry.get_mut() += 1;
+        assert_eq!(map.get(&2), Some(&7));
+        assert!(matches!(map.entry(3), Entry::Vacant(_)));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn entry_rebalancing() {
+        // The reference returned by a vacant entry must still point at the
+        // new value after the rotations that its insertion caused.
+        let n = 1000;
+        let zigzag = (0..n / 2).flat_map(|i| [i, n - 1 - i]);
+        let orders: [Vec<i32>; 3] = [
+            (0..n).collect(),
+            zigzag.collect(),
+            (0..n).map(|i| i * 7919 % n).collect(),
+        ];
+        for order in orders {
+            let mut map = BinaryTreeMap::new();
+            for &key in &order {
+                let Entry::Vacant(entry) = map.entry(key) else {
+                    panic!("{key} inserted twice");
+                };
+                *entry.insert(0) = key * 2;
+            }
+            check_avl(&map.root, (None, None));
+            assert_eq!(map.len(), n as usize);
+            assert!(map.iter().all(|(&key, &value)| value == key * 2));
+        }
+    }
+
+    #[test]
+    fn map_iter() {
+        let mut map = BinaryTreeMap::new();
+        for (i, key) in ["d", "b", "f", "a", "c", "e", "g"].into_iter().enumerate() {
+            map.insert(key, i);
+        }
+        let keys: Vec<&str> = map.iter().map(|(&key, _)| key).collect();
+        assert_eq!(keys, ["a", "b", "c", "d", "e", "f", "g"]);
+        assert_eq!(map.iter().next_back(), Some((&"g", &6)));
+        for (_, value) in map.iter_mut() {
+            *value *= 10;
+        }
+        for (&key, value) in &mut map {
+            if key < "c" {
+                *value += 1;
+            }
+        }
+        let values: Vec<usize> = (&map).into_iter().map(|(_, &value)| value).collect();
+        assert_eq!(values, [31, 11, 40, 0, 50, 20, 60]);
+        let range: Vec<(&&str, &usize)> = map.range("b".."e").rev().collect();
+        assert_eq!(range, [(&"d", &0), (&"c", &40), (&"b", &11)]);
+        assert_eq!(map.iter_mut().count(), 7);
     }
 }
 
@@ -137,4 +1269,11 @@ pub fn main() {
     tree.insert(15);
 
     println!("{:?}", tree);
+    println!("{:?}", tree.iter().collect::<Vec<_>>());
+
+    let mut lengths = BinaryTreeMap::new();
+    for word in ["apple", "fig", "pear", "fig"] {
+        *lengths.entry(word).or_insert(0) += word.len();
+    }
+    println!("{:?}", lengths.iter().collect::<Vec<_>>());
 }
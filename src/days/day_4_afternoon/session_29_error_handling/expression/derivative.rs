@@ -0,0 +1,336 @@
+use thiserror::Error;
+
+use super::optimizer::optimize;
+use super::parser::Expression;
+use super::tokenizer::{Comparison, Op};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DerivativeError {
+    #[error("No derivative is known for function '{0}'")]
+    UnknownDerivative(String),
+    #[error("Can't differentiate an exponent depending on '{0}'")]
+    VariableExponent(String),
+}
+
+/// The derivative of `expr` with respect to the variable `var`, simplified
+/// with [`optimize`].
+///
+/// `expr` is read as a function of real numbers, with `/` as exact division.
+/// Comparisons and boolean operations are piecewise constant and have a
+/// derivative of zero, and so do `min`, `max` and `abs` on either side of
+/// their kinks. Calls to other functions can only be differentiated if
+/// their arguments don't depend on `var`.
+pub fn differentiate(expr: &Expression, var: &str) -> Result<Expression, DerivativeError> {
+    enum Step<'a> {
+        Visit(&'a Expression),
+        Combine(&'a Expression),
+    }
+
+    // Work bottom-up with explicit stacks, so that deeply nested expressions
+    // can be differentiated. Subexpressions not depending on `var` come out
+    // as exactly `0`, which calls rely on.
+    let mut steps = vec![Step::Visit(expr)];
+    let mut derivatives = Vec::new();
+    while let Some(step) = steps.pop() {
+        let expr = match step {
+            Step::Visit(expr) => {
+                steps.push(Step::Combine(expr));
+                match expr {
+                    Expression::Neg(operand) => steps.push(Step::Visit(operand)),
+                    Expression::Operation(lhs, _, rhs) => {
+                        steps.push(Step::Visit(rhs));
+                        steps.push(Step::Visit(lhs));
+                    }
+                    Expression::Call(_, args) => steps.extend(args.iter().rev().map(Step::Visit)),
+                    // The derivatives of the operands of piecewise constant
+                    // operators aren't needed.
+                    _ => {}
+                }
+                continue;
+            }
+            Step::Combine(expr) => expr,
+        };
+        let derivative = match expr {
+            Expression::Var(name) if name == var => Expression::Number(1),
+            Expression::Var(_)
+            | Expression::Number(_)
+            | Expression::Float(_)
+            | Expression::Not(_)
+            | Expression::Compare(..)
+            | Expression::Logical(..) => Expression::Number(0),
+            Expression::Neg(_) => neg(derivatives.pop().unwrap()),
+            Expression::Operation(u, op, v) => {
+                let dv = derivatives.pop().unwrap();
+                let du = derivatives.pop().unwrap();
+                match op {
+                    Op::Add => add(du, dv),
+                    Op::Sub => sub(du, dv),
+                    // (uv)' = u'v + uv'
+                    Op::Mul => add(mul(du, (**v).clone()), mul((**u).clone(), dv)),
+                    // (u/v)' = (u'v - uv') / v²
+                    Op::Div => div(
+                        sub(mul(du, (**v).clone()), mul((**u).clone(), dv)),
+                        mul((**v).clone(), (**v).clone()),
+                    ),
+                    // u % v = u - v q, where the quotient q = (u - u % v) / v
+                    // is piecewise constant.
+                    Op::Rem => {
+                        let rem = binary((**u).clone(), Op::Rem, (**v).clone());
+                        let quotient = div(sub((**u).clone(), rem), (**v).clone());
+                        sub(du, mul(dv, quotient))
+                    }
+                }
+            }
+            Expression::Call(name, args) => {
+                let partials = derivatives.split_off(derivatives.len() - args.len());
+                call_derivative(name, args, partials, var)?
+            }
+        };
+        derivatives.push(derivative);
+    }
+    Ok(optimize(&derivatives.pop().unwrap()))
+}
+
+/// The derivative of `name(args)` by the chain rule, given the derivatives
+/// of the arguments.
+fn call_derivative(
+    name: &str,
+    args: &[Expression],
+    mut partials: Vec<Expression>,
+    var: &str,
+) -> Result<Expression, DerivativeError> {
+    if partials.iter().all(is_zero) {
+        return Ok(Expression::Number(0));
+    }
+    let holds = |lhs: &Expression, comparison, rhs: &Expression| {
+        Expression::Compare(Box::new(lhs.clone()), comparison, Box::new(rhs.clone()))
+    };
+    let derivative = match (name, args) {
+        // abs(u)' = sign(u) u'
+        ("abs", [u]) => {
+            let sign = binary(
+                holds(u, Comparison::Gt, &Expression::Number(0)),
+                Op::Sub,
+                holds(u, Comparison::Lt, &Expression::Number(0)),
+            );
+            mul(sign, partials.pop().unwrap())
+        }
+        // The derivative of whichever argument is selected.
+        ("min" | "max", [a, b]) => {
+            let (a_wins, b_wins) = match name {
+                "min" => (Comparison::Le, Comparison::Gt),
+                _ => (Comparison::Ge, Comparison::Lt),
+            };
+            let db = partials.pop().unwrap();
+            let da = partials.pop().unwrap();
+            add(mul(holds(a, a_wins, b), da), mul(holds(a, b_wins, b), db))
+        }
+        // pow(u, n)' = n pow(u, n - 1) u'
+        ("pow", [u, n]) => {
+            let du = partials.remove(0);
+            if !is_zero(&partials[0]) {
+                return Err(DerivativeError::VariableExponent(var.to_string()));
+            }
+            let lowered = Expression::Call(
+                "pow".to_string(),
+                vec![u.clone(), sub(n.clone(), Expression::Number(1))],
+            );
+            mul(mul(n.clone(), lowered), du)
+        }
+        _ => return Err(DerivativeError::UnknownDerivative(name.to_string())),
+    };
+    Ok(derivative)
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    matches!(expr, Expression::Number(0))
+}
+
+fn is_one(expr: &Expression) -> bool {
+    matches!(expr, Expression::Number(1))
+}
+
+// Constructors that drop the zeros and ones the rules produce all the time,
+// before the expression is simplified as a whole.
+
+fn add(lhs: Expression, rhs: Expression) -> Expression {
+    match (is_zero(&lhs), is_zero(&rhs)) {
+        (true, _) => rhs,
+        (_, true) => lhs,
+        _ => binary(lhs, Op::Add, rhs),
+    }
+}
+
+fn sub(lhs: Expression, rhs: Expression) -> Expression {
+    match (is_zero(&lhs), is_zero(&rhs)) {
+        (_, true) => lhs,
+        (true, _) => neg(rhs),
+        _ => binary(lhs, Op::Sub, rhs),
+    }
+}
+
+fn mul(lhs: Expression, rhs: Expression) -> Expression {
+    if is_zero(&lhs) || is_zero(&rhs) {
+        Expression::Number(0)
+    } else if is_one(&lhs) {
+        rhs
+    } else if is_one(&rhs) {
+        lhs
+    } else {
+        binary(lhs, Op::Mul, rhs)
+    }
+}
+
+fn div(lhs: Expression, rhs: Expression) -> Expression {
+    if is_zero(&lhs) {
+        Expression::Number(0)
+    } else {
+        binary(lhs, Op::Div, rhs)
+    }
+}
+
+fn neg(expr: Expression) -> Expression {
+    if is_zero(&expr) {
+        expr
+    } else {
+        Expression::Neg(Box::new(expr))
+    }
+}
+
+fn binary(lhs: Expression, op: Op, rhs: Expression) -> Expression {
+    Expression::Operation(Box::new(lhs), op, Box::new(rhs))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::super::parser::parse;
+    use super::super::tokenizer::Logical;
+    use super::*;
+
+    /// The value of `expr` in real arithmetic.
+    fn value(expr: &Expression, env: &HashMap<&str, f64>) -> f64 {
+        let truth = |holds: bool| if holds { 1.0 } else { 0.0 };
+        match expr {
+            Expression::Var(name) => env[name.as_str()],
+            Expression::Number(n) => f64::from(*n),
+            Expression::Float(x) => *x,
+            Expression::Neg(operand) => -value(operand, env),
+            Expression::Not(operand) => truth(value(operand, env) == 0.0),
+            Expression::Operation(lhs, op, rhs) => {
+                let (lhs, rhs) = (value(lhs, env), value(rhs, env));
+                match op {
+                    Op::Add => lhs + rhs,
+                    Op::Sub => lhs - rhs,
+                    Op::Mul => lhs * rhs,
+                    Op::Div => lhs / rhs,
+                    Op::Rem => lhs % rhs,
+                }
+            }
+            Expression::Compare(lhs, comparison, rhs) => {
+                let (lhs, rhs) = (value(lhs, env), value(rhs, env));
+                truth(match comparison {
+                    Comparison::Eq => lhs == rhs,
+                    Comparison::Ne => lhs != rhs,
+                    Comparison::Lt => lhs < rhs,
+                    Comparison::Le => lhs <= rhs,
+                    Comparison::Gt => lhs > rhs,
+                    Comparison::Ge => lhs >= rhs,
+                })
+            }
+            Expression::Logical(lhs, logical, rhs) => {
+                let (lhs, rhs) = (value(lhs, env) != 0.0, value(rhs, env) != 0.0);
+                truth(match logical {
+                    Logical::And => lhs && rhs,
+                    Logical::Or => lhs || rhs,
+                })
+            }
+            Expression::Call(name, args) => {
+                let args: Vec<f64> = args.iter().map(|arg| value(arg, env)).collect();
+                match name.as_str() {
+                    "abs" => args[0].abs(),
+                    "min" => args[0].min(args[1]),
+                    "max" => args[0].max(args[1]),
+                    "pow" => args[0].powf(args[1]),
+                    _ => panic!("unknown function {name}"),
+                }
+            }
+        }
+    }
+
+    fn derivative(input: &str, var: &str) -> String {
+        differentiate(&parse(input).unwrap(), var)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(derivative("5", "x"), "0");
+        assert_eq!(derivative("y", "x"), "0");
+        assert_eq!(derivative("x", "x"), "1");
+        assert_eq!(derivative("3*x+y*2", "x"), "3");
+        assert_eq!(derivative("3*x+y*2", "y"), "2");
+        assert_eq!(derivative("x*y-x", "x"), "y-1");
+        assert_eq!(derivative("x*x", "x"), "x+x");
+        assert_eq!(derivative("1/x", "x"), "-1/(x*x)");
+        assert_eq!(derivative("pow(x,3)", "x"), "pow(x,2)*3");
+        assert_eq!(derivative("x*(x%1)", "x"), "x%1+x");
+        assert_eq!(derivative("(x>1)+f(y)", "x"), "0");
+    }
+
+    #[test]
+    fn finite_differences() {
+        let h = 1e-6;
+        for input in [
+            "x*x*x-4*x+7",
+            "(x+1)/(x*x+1)",
+            "x/y-y/x",
+            "-(x*y)+y*y",
+            "1.5*x*x-0.25/x",
+            "pow(x,3)-pow(y,x*0+2)+pow(x,2)/(1+pow(x,4))",
+            "abs(x-1)*x",
+            "max(x,2*x-1)+min(x*x,3)",
+            "(x>0)*x*x+!(x<1)*3",
+            "x%2*x",
+            "x*(x%1)",
+            "(x%1)*(x%1)",
+        ] {
+            let expr = parse(input).unwrap();
+            let derivative = differentiate(&expr, "x").unwrap();
+            for x in [-2.3, -0.7, 0.4, 1.9, 3.6] {
+                let at = |x| value(&expr, &HashMap::from([("x", x), ("y", 1.3)]));
+                let expected = (at(x + h) - at(x - h)) / (2.0 * h);
+                let actual = value(&derivative, &HashMap::from([("x", x), ("y", 1.3)]));
+                assert!(
+                    (actual - expected).abs() <= 1e-4 * (1.0 + expected.abs()),
+                    "d/dx {input} = {derivative} at {x}: {actual}, expected {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let differentiate = |input: &str| differentiate(&parse(input).unwrap(), "x");
+        assert_eq!(
+            differentiate("pow(2,x)"),
+            Err(DerivativeError::VariableExponent("x".to_string()))
+        );
+        assert_eq!(
+            differentiate("1+f(x)"),
+            Err(DerivativeError::UnknownDerivative("f".to_string()))
+        );
+        assert_eq!(
+            differentiate("abs(x,1)"),
+            Err(DerivativeError::UnknownDerivative("abs".to_string()))
+        );
+    }
+
+    #[test]
+    fn long_expressions() {
+        let sum = vec!["x"; 100_000].join("+");
+        assert_eq!(derivative(&sum, "x"), "100000");
+    }
+}
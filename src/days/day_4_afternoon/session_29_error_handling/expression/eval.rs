@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use super::functions::{Function, Functions};
+use super::parser::Expression;
+use super::vm::compile;
+
+/// The values of the variables and the functions an expression is evaluated
+/// with.
+pub trait Environment {
+    /// The value of the variable `name`, or `None` if it is unbound.
+    fn get(&self, name: &str) -> Option<i64>;
+
+    /// The function `name`, or `None` if the environment doesn't define it.
+    /// The built-in functions are available in every environment, unless
+    /// replaced by one of the same name.
+    fn function(&self, _name: &str) -> Option<&Function> {
+        None
+    }
+}
+
+impl Environment for HashMap<String, i64> {
+    fn get(&self, name: &str) -> Option<i64> {
+        HashMap::get(self, name).copied()
+    }
+}
+
+/// An environment with both variables and functions.
+#[derive(Default)]
+pub struct Context {
+    pub variables: HashMap<String, i64>,
+    pub functions: Functions,
+}
+
+impl Environment for Context {
+    fn get(&self, name: &str) -> Option<i64> {
+        self.variables.get(name).copied()
+    }
+
+    fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum EvalError {
+    #[error("Unbound variable '{0}'")]
+    UnboundVariable(String),
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("Function '{name}' takes {expected} arguments but {found} were given")]
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Invalid argument {1} to '{0}'")]
+    InvalidArgument(String, i64),
+    #[error("Maximum call depth exceeded")]
+    CallDepthExceeded,
+    #[error("Floating-point number {0:?} in integer arithmetic")]
+    UnsupportedFloat(f64),
+}
+
+/// Evaluate `expr` with 64-bit integer arithmetic, looking up variables in
+/// `env`.
+///
+/// The expression is compiled to bytecode and run on a stack machine, which
+/// unlike a recursive evaluator handles arbitrarily deep expressions.
+pub fn eval(expr: &Expression, env: &impl Environment) -> Result<i64, EvalError> {
+    compile(expr).run(env)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parser::parse;
+    use super::*;
+
+    fn eval_str(input: &str) -> Result<i64, EvalError> {
+        let env = HashMap::from([("x".to_string(), 6), ("big".to_string(), i64::MAX)]);
+        eval(&parse(input).unwrap(), &env)
+    }
+
+    #[test]
+    fn evaluate() {
+        assert_eq!(eval_str("10-3-2"), Ok(5));
+        assert_eq!(eval_str("1+2*3"), Ok(7));
+        assert_eq!(eval_str("-(1+2)*x%4-10/5"), Ok(-4));
+        assert_eq!(eval_str("-7/2"), Ok(-3));
+        assert_eq!(eval_str("-7%2"), Ok(-1));
+        assert_eq!(eval_str("big"), Ok(i64::MAX));
+        assert_eq!(eval_str("-big-1"), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(eval_str("x==6"), Ok(1));
+        assert_eq!(eval_str("x!=6"), Ok(0));
+        assert_eq!(eval_str("1+2<=3 && 3>2"), Ok(1));
+        assert_eq!(eval_str("x<6 || x>=7"), Ok(0));
+        assert_eq!(eval_str("!x + !0"), Ok(1));
+        assert_eq!(eval_str("x && 2"), Ok(1));
+        assert_eq!(eval_str("(x>1) * 10"), Ok(10));
+        // The right-hand side isn't evaluated if the left-hand side decides.
+        assert_eq!(eval_str("0 && y"), Ok(0));
+        assert_eq!(eval_str("x || 1/0"), Ok(1));
+        assert_eq!(
+            eval_str("1 && y"),
+            Err(EvalError::UnboundVariable("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn eval_errors() {
+        assert_eq!(
+            eval_str("x+y"),
+            Err(EvalError::UnboundVariable("y".to_string()))
+        );
+        assert_eq!(eval_str("big+1"), Err(EvalError::Overflow));
+        assert_eq!(eval_str("-(-big-1)"), Err(EvalError::Overflow));
+        assert_eq!(eval_str("(-big-1)/-1"), Err(EvalError::Overflow));
+        assert_eq!(eval_str("x/(x-6)"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval_str("x%0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval_str("x*1.5"), Err(EvalError::UnsupportedFloat(1.5)));
+    }
+}
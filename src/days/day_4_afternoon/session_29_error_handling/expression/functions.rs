@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use super::eval::{Environment, EvalError};
+use super::parser::Expression;
+use super::vm::{compile, Instruction, Program};
+
+/// The maximum depth of nested calls to user-defined functions.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// The signature of functions implemented in Rust.
+type NativeFn = dyn Fn(&[i64]) -> Result<i64, EvalError>;
+
+/// A function that can be called from expressions.
+pub struct Function {
+    arity: usize,
+    body: Body,
+}
+
+enum Body {
+    /// A function implemented in Rust.
+    Native(Box<NativeFn>),
+    /// A function defined in the expression language, with the names of its
+    /// parameters.
+    Defined(Vec<String>, Program),
+}
+
+impl Function {
+    fn native(arity: usize, f: impl Fn(&[i64]) -> Result<i64, EvalError> + 'static) -> Self {
+        Function {
+            arity,
+            body: Body::Native(Box::new(f)),
+        }
+    }
+
+    /// The number of arguments the function takes.
+    #[allow(dead_code)] // Not needed by the REPL binary.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Call the function, named `name` in `env`, with `args`. `depth` is the
+    /// number of calls to user-defined functions already in progress.
+    pub(super) fn call(
+        &self,
+        name: &str,
+        args: &[i64],
+        env: &dyn Environment,
+        depth: usize,
+    ) -> Result<i64, EvalError> {
+        if args.len() != self.arity {
+            return Err(EvalError::ArityMismatch {
+                name: name.to_string(),
+                expected: self.arity,
+                found: args.len(),
+            });
+        }
+        match &self.body {
+            Body::Native(f) => f(args),
+            Body::Defined(params, program) => {
+                if depth >= MAX_CALL_DEPTH {
+                    return Err(EvalError::CallDepthExceeded);
+                }
+                let frame = Frame {
+                    params,
+                    args,
+                    outer: env,
+                };
+                program.execute(&frame, depth + 1)
+            }
+        }
+    }
+}
+
+/// The environment a user-defined function runs in: its parameters, and the
+/// functions of the environment it was called from.
+struct Frame<'a> {
+    params: &'a [String],
+    args: &'a [i64],
+    outer: &'a dyn Environment,
+}
+
+impl Environment for Frame<'_> {
+    fn get(&self, name: &str) -> Option<i64> {
+        let index = self.params.iter().position(|param| param == name)?;
+        Some(self.args[index])
+    }
+
+    fn function(&self, name: &str) -> Option<&Function> {
+        self.outer.function(name)
+    }
+}
+
+/// The built-in function `name`, available in every environment.
+pub fn builtin(name: &str) -> Option<Function> {
+    let function = match name {
+        "abs" => Function::native(1, |args| args[0].checked_abs().ok_or(EvalError::Overflow)),
+        "min" => Function::native(2, |args| Ok(args[0].min(args[1]))),
+        "max" => Function::native(2, |args| Ok(args[0].max(args[1]))),
+        "pow" => Function::native(2, |args| {
+            let exponent = u32::try_from(args[1])
+                .map_err(|_| EvalError::InvalidArgument("pow".to_string(), args[1]))?;
+            args[0].checked_pow(exponent).ok_or(EvalError::Overflow)
+        }),
+        _ => return None,
+    };
+    Some(function)
+}
+
+/// A registry of functions, on top of the built-in ones.
+#[derive(Default)]
+pub struct Functions(HashMap<String, Function>);
+
+impl Functions {
+    #[allow(dead_code)] // Not needed by the REPL binary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a function implemented in Rust, taking `arity` arguments.
+    /// It replaces any function of the same name, including built-in ones.
+    #[allow(dead_code)] // Not needed by the REPL binary.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        f: impl Fn(&[i64]) -> Result<i64, EvalError> + 'static,
+    ) {
+        self.0.insert(name.to_string(), Function::native(arity, f));
+    }
+
+    /// Define the function `name(params) = body`. The body can only refer to
+    /// the parameters, but can call any function, including itself.
+    pub fn define(
+        &mut self,
+        name: &str,
+        params: Vec<String>,
+        body: &Expression,
+    ) -> Result<(), EvalError> {
+        let program = compile(body);
+        for instruction in &program.code {
+            if let Instruction::Load(index) = instruction {
+                let var = &program.names[*index];
+                if !params.contains(var) {
+                    return Err(EvalError::UnboundVariable(var.clone()));
+                }
+            }
+        }
+        let function = Function {
+            arity: params.len(),
+            body: Body::Defined(params, program),
+        };
+        self.0.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.0.get(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::{eval, Context};
+    use super::super::parser::parse;
+    use super::*;
+
+    fn eval_str(input: &str, context: &Context) -> Result<i64, EvalError> {
+        eval(&parse(input).unwrap(), context)
+    }
+
+    #[test]
+    fn builtins() {
+        let context = Context::default();
+        assert_eq!(eval_str("max(2,abs(-5))", &context), Ok(5));
+        assert_eq!(eval_str("min(2,-5)", &context), Ok(-5));
+        assert_eq!(eval_str("pow(2,10)-1", &context), Ok(1023));
+        assert_eq!(eval_str("pow(10,30)", &context), Err(EvalError::Overflow));
+        assert_eq!(
+            eval_str("pow(2,-1)", &context),
+            Err(EvalError::InvalidArgument("pow".to_string(), -1))
+        );
+    }
+
+    #[test]
+    fn registered() {
+        let mut context = Context::default();
+        let offset = 100;
+        context
+            .functions
+            .register("shift", 1, move |args| Ok(args[0] + offset));
+        context.functions.register("zero", 0, |_| Ok(0));
+        // Registered functions take precedence over built-in ones.
+        context.functions.register("abs", 1, |_| Ok(-1));
+        assert_eq!(eval_str("shift(zero())+abs(3)", &context), Ok(99));
+    }
+
+    #[test]
+    fn defined() {
+        let mut context = Context::default();
+        context.variables.insert("x".to_string(), 3);
+        let mut define = |name: &str, params: &[&str], body: &str| {
+            let params = params.iter().map(|p| p.to_string()).collect();
+            context
+                .functions
+                .define(name, params, &parse(body).unwrap())
+        };
+        define("sq", &["x"], "x*x").unwrap();
+        define("hyp2", &["a", "b"], "sq(a)+sq(b)").unwrap();
+        define("forever", &["n"], "forever(n+1)").unwrap();
+        assert_eq!(
+            define("bad", &["a"], "a+x"),
+            Err(EvalError::UnboundVariable("x".to_string()))
+        );
+
+        assert_eq!(eval_str("hyp2(x,4)-sq(-x)", &context), Ok(16));
+        assert_eq!(
+            eval_str("forever(0)", &context),
+            Err(EvalError::CallDepthExceeded)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let context = Context::default();
+        assert_eq!(
+            eval_str("nope(1)", &context),
+            Err(EvalError::UnknownFunction("nope".to_string()))
+        );
+        assert_eq!(
+            eval_str("max(1)", &context),
+            Err(EvalError::ArityMismatch {
+                name: "max".to_string(),
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            EvalError::ArityMismatch {
+                name: "max".to_string(),
+                expected: 2,
+                found: 3
+            }
+            .to_string(),
+            "Function 'max' takes 2 arguments but 3 were given"
+        );
+    }
+}
@@ -0,0 +1,8 @@
+pub mod derivative;
+pub mod eval;
+pub mod functions;
+pub mod optimizer;
+pub mod parser;
+pub mod repl;
+pub mod tokenizer;
+pub mod vm;
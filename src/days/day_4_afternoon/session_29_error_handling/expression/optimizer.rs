@@ -0,0 +1,393 @@
+use super::parser::Expression;
+use super::tokenizer::{Comparison, Logical, Op};
+
+/// Simplify `expr` by folding constants, eliminating identities like `x+0`,
+/// `x*1` and `x-x`, and moving constants to the end of sums and products so
+/// that they can be combined, e.g. `3+x+4` becomes `x+7`. Comparisons and
+/// boolean operations are folded when their constant operands decide them.
+///
+/// The rewrites are exact for mathematical integers, and also for real
+/// numbers, as derivatives are read. They may remove errors, such as unbound
+/// variables in `x*0`, and may change whether an intermediate result
+/// overflows, but never fold an operation that fails. Divisions of constants
+/// are only folded when they are exact, and `x%1` is kept, as it is only zero
+/// for integers.
+pub fn optimize(expr: &Expression) -> Expression {
+    enum Step<'a> {
+        Visit(&'a Expression),
+        Neg,
+        Not,
+        Binary(Op),
+        Compare(Comparison),
+        Logical(Logical),
+        Call(&'a str, usize),
+    }
+
+    // Rewrite the tree bottom-up with explicit stacks, so that deeply nested
+    // expressions can be optimized.
+    let mut steps = vec![Step::Visit(expr)];
+    let mut results = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Visit(Expression::Number(n)) => results.push(Expression::Number(*n)),
+            Step::Visit(Expression::Float(x)) => results.push(Expression::Float(*x)),
+            Step::Visit(Expression::Var(name)) => results.push(Expression::Var(name.clone())),
+            Step::Visit(Expression::Neg(expr)) => {
+                steps.push(Step::Neg);
+                steps.push(Step::Visit(expr));
+            }
+            Step::Visit(Expression::Not(expr)) => {
+                steps.push(Step::Not);
+                steps.push(Step::Visit(expr));
+            }
+            Step::Visit(Expression::Operation(lhs, op, rhs)) => {
+                steps.push(Step::Binary(*op));
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Visit(lhs));
+            }
+            Step::Visit(Expression::Compare(lhs, comparison, rhs)) => {
+                steps.push(Step::Compare(*comparison));
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Visit(lhs));
+            }
+            Step::Visit(Expression::Logical(lhs, logical, rhs)) => {
+                steps.push(Step::Logical(*logical));
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Visit(lhs));
+            }
+            Step::Visit(Expression::Call(name, args)) => {
+                steps.push(Step::Call(name, args.len()));
+                steps.extend(args.iter().rev().map(Step::Visit));
+            }
+            Step::Neg => {
+                let expr = results.pop().unwrap();
+                results.push(negate(expr));
+            }
+            Step::Not => {
+                let expr = results.pop().unwrap();
+                results.push(match constant(&expr) {
+                    Some(value) => Expression::Number(u32::from(value == 0)),
+                    None => Expression::Not(Box::new(expr)),
+                });
+            }
+            Step::Binary(op) => {
+                let rhs = results.pop().unwrap();
+                let lhs = results.pop().unwrap();
+                results.push(simplify(lhs, op, rhs));
+            }
+            Step::Compare(comparison) => {
+                let rhs = results.pop().unwrap();
+                let lhs = results.pop().unwrap();
+                results.push(compare(lhs, comparison, rhs));
+            }
+            Step::Logical(logical) => {
+                let rhs = results.pop().unwrap();
+                let lhs = results.pop().unwrap();
+                results.push(logical_operation(lhs, logical, rhs));
+            }
+            Step::Call(name, argc) => {
+                let args = results.split_off(results.len() - argc);
+                results.push(Expression::Call(name.to_string(), args));
+            }
+        }
+    }
+    results.pop().unwrap()
+}
+
+/// Simplify the negation of an already simplified expression.
+fn negate(expr: Expression) -> Expression {
+    if let Some(folded) = constant(&expr).and_then(|value| literal(-value)) {
+        return folded;
+    }
+    match into_negated(expr) {
+        Ok(expr) => expr,
+        Err(expr) => Expression::Neg(Box::new(expr)),
+    }
+}
+
+/// Simplify a binary operation on already simplified operands.
+fn simplify(lhs: Expression, op: Op, rhs: Expression) -> Expression {
+    match (op, constant(&lhs), constant(&rhs)) {
+        (_, Some(l), Some(r)) => {
+            return match fold(l, op, r).and_then(literal) {
+                Some(folded) => folded,
+                None => binary(lhs, op, rhs),
+            };
+        }
+        (Op::Add | Op::Sub, _, Some(0)) | (Op::Mul | Op::Div, _, Some(1)) => return lhs,
+        (Op::Add, Some(0), _) | (Op::Mul, Some(1), _) => return rhs,
+        (Op::Sub, Some(0), _) => return negate(rhs),
+        (Op::Mul, Some(0), _) | (Op::Mul, _, Some(0)) => return Expression::Number(0),
+        (Op::Sub, None, None) if lhs == rhs => return Expression::Number(0),
+        // Keep constants last in sums and products.
+        (Op::Add | Op::Mul, Some(_), None) => return simplify(rhs, op, lhs),
+        _ => {}
+    }
+    match op {
+        Op::Add | Op::Sub => simplify_sum(lhs, op, rhs),
+        Op::Mul => simplify_product(lhs, rhs),
+        Op::Div | Op::Rem => binary(lhs, op, rhs),
+    }
+}
+
+/// Simplify a comparison of already simplified operands.
+fn compare(lhs: Expression, comparison: Comparison, rhs: Expression) -> Expression {
+    let (Some(l), Some(r)) = (constant(&lhs), constant(&rhs)) else {
+        return Expression::Compare(Box::new(lhs), comparison, Box::new(rhs));
+    };
+    let holds = match comparison {
+        Comparison::Eq => l == r,
+        Comparison::Ne => l != r,
+        Comparison::Lt => l < r,
+        Comparison::Le => l <= r,
+        Comparison::Gt => l > r,
+        Comparison::Ge => l >= r,
+    };
+    Expression::Number(u32::from(holds))
+}
+
+/// Simplify a boolean operation on already simplified operands. The
+/// right-hand side is dropped if a constant left-hand side decides the
+/// result, as it would never be evaluated.
+fn logical_operation(lhs: Expression, logical: Logical, rhs: Expression) -> Expression {
+    let truth = |expr: &Expression| constant(expr).map(|value| value != 0);
+    match (logical, truth(&lhs), truth(&rhs)) {
+        (Logical::And, Some(false), _) => Expression::Number(0),
+        (Logical::Or, Some(true), _) => Expression::Number(1),
+        (_, Some(_), Some(r)) => Expression::Number(u32::from(r)),
+        _ => Expression::Logical(Box::new(lhs), logical, Box::new(rhs)),
+    }
+}
+
+/// Combine the constants of a sum or difference into a single trailing one.
+fn simplify_sum(lhs: Expression, op: Op, rhs: Expression) -> Expression {
+    let sign = if op == Op::Add { 1 } else { -1 };
+    if let Some(r) = constant(&rhs) {
+        // (a ± c1) ± c2 => a ± (c1 ± c2)
+        let (base, c1) = match trailing_term(lhs) {
+            Ok(term) => term,
+            Err(lhs) => (lhs, 0),
+        };
+        return match c1.checked_add(sign * r) {
+            Some(total) if literal(total.abs()).is_some() => add_constant(base, total),
+            // Too large to be combined, keep the constants apart.
+            _ => binary(add_constant(base, c1), op, rhs),
+        };
+    }
+    match trailing_term(lhs) {
+        // (a ± c) ± r => (a ± r) ± c
+        Ok((base, c)) => {
+            let inner = simplify(base, op, rhs);
+            with_constant(inner, c)
+        }
+        Err(lhs) => match trailing_term(rhs) {
+            // l ± (b ± c) => (l ± b) ± c
+            Ok((base, c)) => {
+                let inner = simplify(lhs, op, base);
+                with_constant(inner, sign * c)
+            }
+            Err(rhs) => binary(lhs, op, rhs),
+        },
+    }
+}
+
+/// Combine the constants of a product into a single trailing one.
+fn simplify_product(lhs: Expression, rhs: Expression) -> Expression {
+    let factor = |expr: Expression| match into_operation(expr) {
+        Ok((base, Op::Mul, c)) if constant(&c).is_some() => Ok((base, c)),
+        Ok((l, op, r)) => Err(binary(l, op, r)),
+        Err(expr) => Err(expr),
+    };
+    match (factor(lhs), constant(&rhs)) {
+        // (a * c1) * c2 => a * (c1 * c2)
+        (Ok((base, c1)), Some(c2)) => {
+            match constant(&c1).unwrap().checked_mul(c2).and_then(literal) {
+                Some(c) => simplify(base, Op::Mul, c),
+                None => binary(binary(base, Op::Mul, c1), Op::Mul, rhs),
+            }
+        }
+        // (a * c) * r => (a * r) * c
+        (Ok((base, c)), None) => simplify(simplify(base, Op::Mul, rhs), Op::Mul, c),
+        (Err(lhs), _) => match factor(rhs) {
+            // l * (b * c) => (l * b) * c
+            Ok((base, c)) => simplify(simplify(lhs, Op::Mul, base), Op::Mul, c),
+            Err(rhs) => binary(lhs, Op::Mul, rhs),
+        },
+    }
+}
+
+/// Add the constant `value` to a simplified sum, combining it with any
+/// trailing constant.
+fn with_constant(expr: Expression, value: i64) -> Expression {
+    let op = if value < 0 { Op::Sub } else { Op::Add };
+    simplify(expr, op, literal(value.abs()).unwrap())
+}
+
+/// `expr ± value`, written with a positive literal.
+fn add_constant(expr: Expression, value: i64) -> Expression {
+    match value {
+        0 => expr,
+        _ if value < 0 => binary(expr, Op::Sub, literal(-value).unwrap()),
+        _ => binary(expr, Op::Add, literal(value).unwrap()),
+    }
+}
+
+/// Split `a ± c` into `a` and the signed constant `±c`.
+fn trailing_term(expr: Expression) -> Result<(Expression, i64), Expression> {
+    match into_operation(expr) {
+        Ok((base, op @ (Op::Add | Op::Sub), c)) if constant(&c).is_some() => {
+            let c = constant(&c).unwrap();
+            Ok((base, if op == Op::Add { c } else { -c }))
+        }
+        Ok((l, op, r)) => Err(binary(l, op, r)),
+        Err(expr) => Err(expr),
+    }
+}
+
+/// The value of a literal number, or of a negated one.
+fn constant(expr: &Expression) -> Option<i64> {
+    match expr {
+        Expression::Number(n) => Some(i64::from(*n)),
+        Expression::Neg(expr) => match **expr {
+            Expression::Number(n) => Some(-i64::from(n)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The literal for `value`, or `None` if it is too large to be written as
+/// one.
+fn literal(value: i64) -> Option<Expression> {
+    let n = Expression::Number(u32::try_from(value.unsigned_abs()).ok()?);
+    Some(if value < 0 {
+        Expression::Neg(Box::new(n))
+    } else {
+        n
+    })
+}
+
+/// Evaluate a binary operation on constants, or `None` if it fails.
+fn fold(lhs: i64, op: Op, rhs: i64) -> Option<i64> {
+    match op {
+        Op::Add => lhs.checked_add(rhs),
+        Op::Sub => lhs.checked_sub(rhs),
+        Op::Mul => lhs.checked_mul(rhs),
+        Op::Div => lhs
+            .checked_rem(rhs)
+            .filter(|&rem| rem == 0)
+            .and_then(|_| lhs.checked_div(rhs)),
+        Op::Rem => lhs.checked_rem(rhs),
+    }
+}
+
+fn binary(lhs: Expression, op: Op, rhs: Expression) -> Expression {
+    Expression::Operation(Box::new(lhs), op, Box::new(rhs))
+}
+
+/// Take an expression apart. `Expression` implements `Drop`, so this can't
+/// be done by pattern matching.
+fn into_operation(mut expr: Expression) -> Result<(Expression, Op, Expression), Expression> {
+    match &mut expr {
+        Expression::Operation(lhs, op, rhs) => Ok((take(lhs), *op, take(rhs))),
+        _ => Err(expr),
+    }
+}
+
+fn into_negated(mut expr: Expression) -> Result<Expression, Expression> {
+    match &mut expr {
+        Expression::Neg(inner) => Ok(take(inner)),
+        _ => Err(expr),
+    }
+}
+
+fn take(expr: &mut Expression) -> Expression {
+    std::mem::replace(expr, Expression::Number(0))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::super::eval::eval;
+    use super::super::parser::parse;
+    use super::*;
+
+    fn optimized(input: &str) -> String {
+        optimize(&parse(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn constant_folding() {
+        assert_eq!(optimized("1+2*3"), "7");
+        assert_eq!(optimized("2-5"), "-3");
+        assert_eq!(optimized("-(4*-2)"), "8");
+        assert_eq!(optimized("x*(7%4-10/5)"), "x");
+        // Operations that fail or don't fit in a literal are left alone.
+        assert_eq!(optimized("x+1/0"), "x+1/0");
+        assert_eq!(optimized("100000*100000"), "100000*100000");
+        assert_eq!(optimized("x+8/-2"), "x-4");
+        assert_eq!(optimized("7/2"), "7/2");
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(optimized("x+0"), "x");
+        assert_eq!(optimized("0+x"), "x");
+        assert_eq!(optimized("0-x"), "-x");
+        assert_eq!(optimized("--x"), "x");
+        assert_eq!(optimized("x*1/1"), "x");
+        assert_eq!(optimized("y*0+x%1"), "x%1");
+        assert_eq!(optimized("x-x"), "0");
+        assert_eq!(optimized("(x*y+1)-(x*y+1)"), "0");
+        assert_eq!(optimized("x/x"), "x/x");
+    }
+
+    #[test]
+    fn reassociation() {
+        assert_eq!(optimized("3+4+y"), "y+7");
+        assert_eq!(optimized("3+x+4"), "x+7");
+        assert_eq!(optimized("x-3+1"), "x-2");
+        assert_eq!(optimized("1+x-1"), "x");
+        assert_eq!(optimized("(x+1)+(y+2)"), "x+y+3");
+        assert_eq!(optimized("(x+1)-(y+2)"), "x-y-1");
+        assert_eq!(optimized("2*x*3"), "x*6");
+        assert_eq!(optimized("(2*x)*(y*3)"), "x*y*6");
+        assert_eq!(optimized("x*-2*3"), "x*-6");
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(optimized("1+1==2"), "1");
+        assert_eq!(optimized("3<-4"), "0");
+        assert_eq!(optimized("!(2-2)"), "1");
+        assert_eq!(optimized("0&&x"), "0");
+        assert_eq!(optimized("5||1/0"), "1");
+        assert_eq!(optimized("1&&2>1"), "1");
+        assert_eq!(optimized("x<1+1&&0"), "x<2&&0");
+        assert_eq!(optimized("!(x+0)"), "!x");
+    }
+
+    #[test]
+    fn preserves_values() {
+        let env = HashMap::from([("x".to_string(), 7), ("y".to_string(), -3)]);
+        for input in [
+            "3+x*2-(4-y)*1",
+            "x-(y-(x-(y-5)))",
+            "(2*x)*(y*3)-x*0+-(x%3)",
+            "x/2*4+1-y/3",
+            "x>y==1&&!(y>=0)||x-7",
+        ] {
+            let expr = parse(input).unwrap();
+            let optimized = optimize(&expr);
+            assert_eq!(eval(&optimized, &env), eval(&expr, &env), "{input}");
+            assert_eq!(parse(&optimized.to_string()).unwrap(), optimized);
+        }
+    }
+
+    #[test]
+    fn long_expressions() {
+        let sum = format!("x{}", "+1".repeat(100_000));
+        assert_eq!(optimized(&sum), "x+100000");
+    }
+}
@@ -0,0 +1,1015 @@
+use std::fmt;
+use std::iter::Peekable;
+
+use thiserror::Error;
+
+use super::tokenizer::{
+    render_diagnostic, tokenize, Comparison, Logical, Op, Span, Token, TokenKind, Tokenizer,
+    TokenizerError,
+};
+
+/// An expression in the expression language.
+pub enum Expression {
+    /// A reference to a variable.
+    Var(String),
+    /// A literal number.
+    Number(u32),
+    /// A literal floating-point number.
+    Float(f64),
+    /// A binary operation.
+    Operation(Box<Expression>, Op, Box<Expression>),
+    /// A negated expression.
+    Neg(Box<Expression>),
+    /// A function call.
+    Call(String, Vec<Expression>),
+    /// A comparison, which is 1 if it holds and 0 otherwise.
+    Compare(Box<Expression>, Comparison, Box<Expression>),
+    /// A short-circuiting boolean operation on values that are true if
+    /// non-zero, which is 1 if it holds and 0 otherwise.
+    Logical(Box<Expression>, Logical, Box<Expression>),
+    /// A boolean negation.
+    Not(Box<Expression>),
+}
+
+/// An infix operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Infix {
+    Arithmetic(Op),
+    Comparison(Comparison),
+    Logical(Logical),
+}
+
+impl Infix {
+    fn from_token(kind: &TokenKind) -> Option<Infix> {
+        match kind {
+            TokenKind::Operator(op) => Some(Infix::Arithmetic(*op)),
+            TokenKind::Comparison(comparison) => Some(Infix::Comparison(*comparison)),
+            TokenKind::Logical(logical) => Some(Infix::Logical(*logical)),
+            _ => None,
+        }
+    }
+
+    /// The left and right binding power of the operator. The right power
+    /// being higher makes the operator left-associative.
+    fn binding_power(self) -> (u8, u8) {
+        match self {
+            Infix::Logical(Logical::Or) => (1, 2),
+            Infix::Logical(Logical::And) => (3, 4),
+            Infix::Comparison(_) => (5, 6),
+            Infix::Arithmetic(Op::Add | Op::Sub) => (7, 8),
+            Infix::Arithmetic(Op::Mul | Op::Div | Op::Rem) => (9, 10),
+        }
+    }
+
+    fn apply(self, lhs: Expression, rhs: Expression) -> Expression {
+        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+        match self {
+            Infix::Arithmetic(op) => Expression::Operation(lhs, op, rhs),
+            Infix::Comparison(comparison) => Expression::Compare(lhs, comparison, rhs),
+            Infix::Logical(logical) => Expression::Logical(lhs, logical, rhs),
+        }
+    }
+}
+
+impl fmt::Display for Infix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Infix::Arithmetic(op) => write!(f, "{op}"),
+            Infix::Comparison(comparison) => write!(f, "{comparison}"),
+            Infix::Logical(logical) => write!(f, "{logical}"),
+        }
+    }
+}
+
+/// The binding power of unary minus and `!`, which bind tighter than any
+/// infix operator.
+const PREFIX_BINDING_POWER: u8 = 11;
+
+/// Print the expression in infix form with as few parentheses as possible,
+/// such that parsing the output gives back the same expression.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        enum Piece<'a> {
+            Expr(&'a Expression),
+            Text(&'static str),
+            Infix(Infix),
+        }
+
+        // An operand needs parentheses if its operator binds less tightly
+        // than the one it is an operand of, or equally tightly on the right
+        // since operators are left-associative.
+        let needs_parens =
+            |operand: &Expression, infix: Infix, right: bool| match operand.as_infix() {
+                Some((_, inner, _)) => {
+                    let (inner_bp, _) = inner.binding_power();
+                    let (outer_bp, _) = infix.binding_power();
+                    inner_bp < outer_bp || (right && inner_bp == outer_bp)
+                }
+                None => false,
+            };
+
+        // Print iteratively so that deeply nested expressions can be printed.
+        let mut pieces = vec![Piece::Expr(self)];
+        while let Some(piece) = pieces.pop() {
+            let expr = match piece {
+                Piece::Expr(expr) => expr,
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                Piece::Infix(infix) => {
+                    write!(f, "{infix}")?;
+                    continue;
+                }
+            };
+            match expr {
+                Expression::Var(name) => f.write_str(name)?,
+                Expression::Number(n) => write!(f, "{n}")?,
+                // The debug format always has a fraction or an exponent.
+                Expression::Float(x) => write!(f, "{x:?}")?,
+                Expression::Neg(operand) | Expression::Not(operand) => {
+                    let prefix = if let Expression::Neg(_) = expr {
+                        "-"
+                    } else {
+                        "!"
+                    };
+                    f.write_str(prefix)?;
+                    if operand.as_infix().is_some() {
+                        pieces.extend([Piece::Text(")"), Piece::Expr(operand), Piece::Text("(")]);
+                    } else {
+                        pieces.push(Piece::Expr(operand));
+                    }
+                }
+                Expression::Call(name, args) => {
+                    f.write_str(name)?;
+                    pieces.push(Piece::Text(")"));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        pieces.push(Piece::Expr(arg));
+                        if i > 0 {
+                            pieces.push(Piece::Text(","));
+                        }
+                    }
+                    pieces.push(Piece::Text("("));
+                }
+                Expression::Operation(..) | Expression::Compare(..) | Expression::Logical(..) => {
+                    let (lhs, infix, rhs) = expr.as_infix().unwrap();
+                    for (operand, right) in [(rhs, true), (lhs, false)] {
+                        if needs_parens(operand, infix, right) {
+                            pieces.extend([
+                                Piece::Text(")"),
+                                Piece::Expr(operand),
+                                Piece::Text("("),
+                            ]);
+                        } else {
+                            pieces.push(Piece::Expr(operand));
+                        }
+                        if right {
+                            pieces.push(Piece::Infix(infix));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Dropping a deeply nested expression recursively could overflow the stack,
+// so the subexpressions are detached and dropped one at a time instead.
+impl Drop for Expression {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut expr) = stack.pop() {
+            expr.take_children(&mut stack);
+        }
+    }
+}
+
+// Cloning is iterative for the same reason, copying the leaves and then
+// rebuilding each node from the copies of its operands.
+impl Clone for Expression {
+    fn clone(&self) -> Self {
+        enum Step<'a> {
+            Visit(&'a Expression),
+            Build(&'a Expression),
+        }
+
+        let mut steps = vec![Step::Visit(self)];
+        let mut copies = Vec::new();
+        while let Some(step) = steps.pop() {
+            let expr = match step {
+                Step::Visit(expr) => {
+                    steps.push(Step::Build(expr));
+                    match expr {
+                        Expression::Var(_) | Expression::Number(_) | Expression::Float(_) => {}
+                        Expression::Neg(operand) | Expression::Not(operand) => {
+                            steps.push(Step::Visit(operand))
+                        }
+                        Expression::Operation(lhs, _, rhs)
+                        | Expression::Compare(lhs, _, rhs)
+                        | Expression::Logical(lhs, _, rhs) => {
+                            steps.push(Step::Visit(rhs));
+                            steps.push(Step::Visit(lhs));
+                        }
+                        Expression::Call(_, args) => {
+                            steps.extend(args.iter().rev().map(Step::Visit))
+                        }
+                    }
+                    continue;
+                }
+                Step::Build(expr) => expr,
+            };
+            let mut operand = || Box::new(copies.pop().unwrap());
+            let copy = match expr {
+                Expression::Var(name) => Expression::Var(name.clone()),
+                Expression::Number(n) => Expression::Number(*n),
+                Expression::Float(x) => Expression::Float(*x),
+                Expression::Neg(_) => Expression::Neg(operand()),
+                Expression::Not(_) => Expression::Not(operand()),
+                Expression::Operation(_, op, _) => {
+                    let rhs = operand();
+                    Expression::Operation(operand(), *op, rhs)
+                }
+                Expression::Compare(_, comparison, _) => {
+                    let rhs = operand();
+                    Expression::Compare(operand(), *comparison, rhs)
+                }
+                Expression::Logical(_, logical, _) => {
+                    let rhs = operand();
+                    Expression::Logical(operand(), *logical, rhs)
+                }
+                Expression::Call(name, args) => {
+                    let args = copies.split_off(copies.len() - args.len());
+                    Expression::Call(name.clone(), args)
+                }
+            };
+            copies.push(copy);
+        }
+        copies.pop().unwrap()
+    }
+}
+
+// Comparing is iterative too, checking pairs of subexpressions from a stack.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pairs = vec![(self, other)];
+        while let Some(pair) = pairs.pop() {
+            match pair {
+                (Expression::Var(a), Expression::Var(b)) if a == b => {}
+                (Expression::Number(a), Expression::Number(b)) if a == b => {}
+                (Expression::Float(a), Expression::Float(b)) if a == b => {}
+                (Expression::Neg(a), Expression::Neg(b))
+                | (Expression::Not(a), Expression::Not(b)) => pairs.push((a, b)),
+                (Expression::Operation(a, x, b), Expression::Operation(c, y, d)) if x == y => {
+                    pairs.extend([(&**a, &**c), (b, d)])
+                }
+                (Expression::Compare(a, x, b), Expression::Compare(c, y, d)) if x == y => {
+                    pairs.extend([(&**a, &**c), (b, d)])
+                }
+                (Expression::Logical(a, x, b), Expression::Logical(c, y, d)) if x == y => {
+                    pairs.extend([(&**a, &**c), (b, d)])
+                }
+                (Expression::Call(a, xs), Expression::Call(b, ys))
+                    if a == b && xs.len() == ys.len() =>
+                {
+                    pairs.extend(xs.iter().zip(ys))
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+// Debug formatting is iterative as well, producing the same output as a
+// derived implementation, with `{:#?}` included.
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        enum Piece<'a> {
+            Expr(&'a Expression, usize),
+            Field(&'a dyn fmt::Debug),
+            Args(&'a [Expression], usize),
+            /// The start of an item of a list nested this deep.
+            Item(usize),
+            /// The end of an item, and whether it is the last one.
+            ItemEnd(bool),
+            Close(&'static str, usize),
+        }
+
+        /// Open a list nested `depth` deep, pushing its items and the text
+        /// closing it.
+        fn open<'a>(
+            f: &mut fmt::Formatter,
+            pieces: &mut Vec<Piece<'a>>,
+            (open, close): (&str, &'static str),
+            items: Vec<Piece<'a>>,
+            depth: usize,
+        ) -> fmt::Result {
+            f.write_str(open)?;
+            if f.alternate() {
+                f.write_str("\n")?;
+            }
+            pieces.push(Piece::Close(close, depth));
+            let last = items.len() - 1;
+            for (i, item) in items.into_iter().enumerate().rev() {
+                pieces.extend([Piece::ItemEnd(i == last), item, Piece::Item(depth + 1)]);
+            }
+            Ok(())
+        }
+
+        fn indent(f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+            if f.alternate() {
+                write!(f, "{:1$}", "", 4 * depth)
+            } else {
+                Ok(())
+            }
+        }
+
+        let mut pieces = vec![Piece::Expr(self, 0)];
+        while let Some(piece) = pieces.pop() {
+            let (expr, depth) = match piece {
+                Piece::Expr(expr, depth) => (expr, depth),
+                Piece::Field(field) => {
+                    field.fmt(f)?;
+                    continue;
+                }
+                Piece::Args([], _) => {
+                    f.write_str("[]")?;
+                    continue;
+                }
+                Piece::Args(args, depth) => {
+                    let args = args.iter().map(|arg| Piece::Expr(arg, depth + 1));
+                    open(f, &mut pieces, ("[", "]"), args.collect(), depth)?;
+                    continue;
+                }
+                Piece::Item(depth) => {
+                    indent(f, depth)?;
+                    continue;
+                }
+                Piece::ItemEnd(last) => {
+                    if f.alternate() {
+                        f.write_str(",\n")?;
+                    } else if !last {
+                        f.write_str(", ")?;
+                    }
+                    continue;
+                }
+                Piece::Close(close, depth) => {
+                    indent(f, depth)?;
+                    f.write_str(close)?;
+                    continue;
+                }
+            };
+            let operand = |operand| Piece::Expr(operand, depth + 1);
+            let (name, fields) = match expr {
+                Expression::Var(name) => ("Var", vec![Piece::Field(name)]),
+                Expression::Number(n) => ("Number", vec![Piece::Field(n)]),
+                Expression::Float(x) => ("Float", vec![Piece::Field(x)]),
+                Expression::Neg(expr) => ("Neg", vec![operand(expr)]),
+                Expression::Not(expr) => ("Not", vec![operand(expr)]),
+                Expression::Operation(lhs, op, rhs) => (
+                    "Operation",
+                    vec![operand(lhs), Piece::Field(op), operand(rhs)],
+                ),
+                Expression::Compare(lhs, comparison, rhs) => (
+                    "Compare",
+                    vec![operand(lhs), Piece::Field(comparison), operand(rhs)],
+                ),
+                Expression::Logical(lhs, logical, rhs) => (
+                    "Logical",
+                    vec![operand(lhs), Piece::Field(logical), operand(rhs)],
+                ),
+                Expression::Call(name, args) => (
+                    "Call",
+                    vec![Piece::Field(name), Piece::Args(args, depth + 1)],
+                ),
+            };
+            f.write_str(name)?;
+            open(f, &mut pieces, ("(", ")"), fields, depth)?;
+        }
+        Ok(())
+    }
+}
+
+impl Expression {
+    /// The operands and operator of an infix expression.
+    fn as_infix(&self) -> Option<(&Expression, Infix, &Expression)> {
+        match self {
+            Expression::Operation(lhs, op, rhs) => Some((lhs, Infix::Arithmetic(*op), rhs)),
+            Expression::Compare(lhs, comparison, rhs) => {
+                Some((lhs, Infix::Comparison(*comparison), rhs))
+            }
+            Expression::Logical(lhs, logical, rhs) => Some((lhs, Infix::Logical(*logical), rhs)),
+            _ => None,
+        }
+    }
+
+    /// Move the subexpressions onto `stack`, leaving leaves in their place.
+    fn take_children(&mut self, stack: &mut Vec<Expression>) {
+        let leaf = || Expression::Number(0);
+        match self {
+            Expression::Var(_) | Expression::Number(_) | Expression::Float(_) => {}
+            Expression::Operation(lhs, _, rhs)
+            | Expression::Compare(lhs, _, rhs)
+            | Expression::Logical(lhs, _, rhs) => {
+                stack.push(std::mem::replace(lhs, leaf()));
+                stack.push(std::mem::replace(rhs, leaf()));
+            }
+            Expression::Neg(expr) | Expression::Not(expr) => {
+                stack.push(std::mem::replace(expr, leaf()))
+            }
+            Expression::Call(_, args) => stack.append(args),
+        }
+    }
+}
+
+/// Parse an integer literal, with an optional `0x` or `0b` prefix and `_`
+/// separators.
+fn parse_integer(text: &str) -> Result<u32, std::num::ParseIntError> {
+    let digits = text.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+    u32::from_str_radix(digits, radix)
+}
+
+#[derive(Debug, Error)]
+pub enum ParserError {
+    #[error("Tokenizer error: {0}")]
+    TokenizerError(#[from] TokenizerError),
+    #[error("Unexpected end of input")]
+    UnexpectedEOF(Span),
+    #[error("Unexpected token `{}`", .0.kind)]
+    UnexpectedToken(Token),
+    #[error("Invalid number: {0}")]
+    InvalidNumber(#[source] std::num::ParseIntError, Span),
+    #[error("Invalid floating-point number: {0}")]
+    InvalidFloat(#[source] std::num::ParseFloatError, Span),
+    #[error("Floating-point number out of range")]
+    FloatOutOfRange(Span),
+    #[error("Unbalanced parenthesis")]
+    UnbalancedParen(Span),
+}
+
+impl ParserError {
+    /// Where in the input the error occurred.
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::TokenizerError(e) => e.span(),
+            ParserError::UnexpectedToken(tok) => tok.span,
+            ParserError::UnexpectedEOF(span)
+            | ParserError::InvalidNumber(_, span)
+            | ParserError::InvalidFloat(_, span)
+            | ParserError::FloatOutOfRange(span)
+            | ParserError::UnbalancedParen(span) => *span,
+        }
+    }
+
+    /// Render the error as a diagnostic quoting the part of `input`, which
+    /// must be the parsed input, that caused it.
+    pub fn render(&self, input: &str) -> String {
+        render_diagnostic(input, self.span(), &self.to_string())
+    }
+}
+
+/// A precedence-climbing (Pratt) parser over the tokens of an expression.
+struct Parser<'a> {
+    tokens: Peekable<Tokenizer<'a>>,
+    /// The length of the input, where an unexpected end of input is reported.
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Look at the next token without consuming it.
+    fn peek(&mut self) -> Result<Option<&Token>, ParserError> {
+        match self.tokens.peek() {
+            None => Ok(None),
+            Some(Ok(tok)) => Ok(Some(tok)),
+            Some(Err(e)) => Err(e.clone().into()),
+        }
+    }
+
+    /// Consume the next token.
+    fn next(&mut self) -> Result<Option<Token>, ParserError> {
+        Ok(self.tokens.next().transpose()?)
+    }
+
+    /// Parse the next operand: a number, variable or call without arguments,
+    /// after any number of unary minuses, opening parentheses and calls up to
+    /// their first argument, which are pushed onto `stack`.
+    fn parse_operand(&mut self, stack: &mut Vec<Pending>) -> Result<Expression, ParserError> {
+        loop {
+            let Some(tok) = self.next()? else {
+                return Err(ParserError::UnexpectedEOF(Span::new(self.end, self.end)));
+            };
+            match tok.kind {
+                TokenKind::Number(num) => {
+                    return match parse_integer(&num) {
+                        Ok(v) => Ok(Expression::Number(v)),
+                        Err(e) => Err(ParserError::InvalidNumber(e, tok.span)),
+                    }
+                }
+                TokenKind::Float(num) => {
+                    // Infinity has no literal, so it would not survive printing.
+                    return match num.replace('_', "").parse::<f64>() {
+                        Ok(x) if x.is_finite() => Ok(Expression::Float(x)),
+                        Ok(_) => Err(ParserError::FloatOutOfRange(tok.span)),
+                        Err(e) => Err(ParserError::InvalidFloat(e, tok.span)),
+                    };
+                }
+                TokenKind::Identifier(ident) => {
+                    if !matches!(
+                        self.peek()?.map(|tok| &tok.kind),
+                        Some(TokenKind::LeftParen)
+                    ) {
+                        return Ok(Expression::Var(ident));
+                    }
+                    let open = self.next()?.unwrap().span;
+                    if let Some(TokenKind::RightParen) = self.peek()?.map(|tok| &tok.kind) {
+                        self.next()?;
+                        return Ok(Expression::Call(ident, Vec::new()));
+                    }
+                    stack.push(Pending::Call(ident, Vec::new(), open));
+                }
+                TokenKind::Operator(Op::Sub) => stack.push(Pending::Neg),
+                TokenKind::Not => stack.push(Pending::Not),
+                TokenKind::LeftParen => stack.push(Pending::Paren(tok.span)),
+                TokenKind::Operator(_)
+                | TokenKind::Comparison(_)
+                | TokenKind::Logical(_)
+                | TokenKind::RightParen
+                | TokenKind::Comma => return Err(ParserError::UnexpectedToken(tok)),
+            }
+        }
+    }
+
+    /// Parse a whole expression.
+    ///
+    /// Rather than recursing for each operand, operators still waiting for
+    /// their right-hand side are kept on an explicit stack, so arbitrarily
+    /// long or deeply nested expressions can be parsed.
+    fn parse_expr(&mut self) -> Result<Expression, ParserError> {
+        let mut stack = Vec::new();
+        let mut lhs = self.parse_operand(&mut stack)?;
+        loop {
+            let min_bp = stack.last().map_or(0, Pending::binding_power);
+            let next = self.peek()?.map(|tok| &tok.kind);
+            if let Some(infix) = next.and_then(Infix::from_token) {
+                let (left_bp, right_bp) = infix.binding_power();
+                if left_bp >= min_bp {
+                    self.next()?;
+                    stack.push(Pending::Binary(lhs, infix, right_bp));
+                    lhs = self.parse_operand(&mut stack)?;
+                    continue;
+                }
+            } else if !matches!(next, None | Some(TokenKind::RightParen | TokenKind::Comma)) {
+                let tok = self.next()?.unwrap();
+                return Err(ParserError::UnexpectedToken(tok));
+            }
+
+            // The operand is complete: apply it to the innermost pending
+            // operator.
+            lhs = match stack.pop() {
+                Some(Pending::Neg) => Expression::Neg(Box::new(lhs)),
+                Some(Pending::Not) => Expression::Not(Box::new(lhs)),
+                Some(Pending::Binary(left, infix, _)) => infix.apply(left, lhs),
+                Some(Pending::Paren(open)) => match self.next()? {
+                    Some(Token {
+                        kind: TokenKind::RightParen,
+                        ..
+                    }) => lhs,
+                    Some(tok) => return Err(ParserError::UnexpectedToken(tok)),
+                    None => return Err(ParserError::UnbalancedParen(open)),
+                },
+                Some(Pending::Call(name, mut args, open)) => {
+                    args.push(lhs);
+                    match self.next()? {
+                        Some(Token {
+                            kind: TokenKind::RightParen,
+                            ..
+                        }) => Expression::Call(name, args),
+                        Some(Token {
+                            kind: TokenKind::Comma,
+                            ..
+                        }) => {
+                            stack.push(Pending::Call(name, args, open));
+                            self.parse_operand(&mut stack)?
+                        }
+                        Some(tok) => return Err(ParserError::UnexpectedToken(tok)),
+                        None => return Err(ParserError::UnbalancedParen(open)),
+                    }
+                }
+                None => return Ok(lhs),
+            };
+        }
+    }
+}
+
+/// Something waiting for the operand being parsed.
+enum Pending {
+    /// A unary minus.
+    Neg,
+    /// A `!`.
+    Not,
+    /// An infix operator with its left-hand side and right binding power.
+    Binary(Expression, Infix, u8),
+    /// An opening parenthesis.
+    Paren(Span),
+    /// A call with its name, the arguments parsed so far and its opening
+    /// parenthesis.
+    Call(String, Vec<Expression>, Span),
+}
+
+impl Pending {
+    /// How tightly an operator must bind to take the operand being parsed
+    /// as its left-hand side instead.
+    fn binding_power(&self) -> u8 {
+        match self {
+            Pending::Neg | Pending::Not => PREFIX_BINDING_POWER,
+            Pending::Binary(_, _, right_bp) => *right_bp,
+            Pending::Paren(_) | Pending::Call(..) => 0,
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Expression, ParserError> {
+    let mut parser = Parser {
+        tokens: tokenize(input).peekable(),
+        end: input.len(),
+    };
+    let expr = parser.parse_expr()?;
+    match parser.next()? {
+        None => Ok(expr),
+        // A complete expression can only be followed by an unopened closing
+        // parenthesis or a stray comma.
+        Some(Token {
+            kind: TokenKind::RightParen,
+            span,
+        }) => Err(ParserError::UnbalancedParen(span)),
+        Some(tok) => Err(ParserError::UnexpectedToken(tok)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn num(n: u32) -> Expression {
+        Expression::Number(n)
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    fn binary(lhs: Expression, op: Op, rhs: Expression) -> Expression {
+        Expression::Operation(Box::new(lhs), op, Box::new(rhs))
+    }
+
+    fn neg(expr: Expression) -> Expression {
+        Expression::Neg(Box::new(expr))
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(
+            parse("10-3-2").unwrap(),
+            binary(binary(num(10), Op::Sub, num(3)), Op::Sub, num(2))
+        );
+        assert_eq!(
+            parse("8/4/2").unwrap(),
+            binary(binary(num(8), Op::Div, num(4)), Op::Div, num(2))
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("1+2*3").unwrap(),
+            binary(num(1), Op::Add, binary(num(2), Op::Mul, num(3)))
+        );
+        assert_eq!(
+            parse("a%b-c/d").unwrap(),
+            binary(
+                binary(var("a"), Op::Rem, var("b")),
+                Op::Sub,
+                binary(var("c"), Op::Div, var("d"))
+            )
+        );
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(parse("-2*3").unwrap(), binary(neg(num(2)), Op::Mul, num(3)));
+        assert_eq!(parse("--x").unwrap(), neg(neg(var("x"))));
+        assert_eq!(parse("1--2").unwrap(), binary(num(1), Op::Sub, neg(num(2))));
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(
+            parse("0x1F + 0b101").unwrap(),
+            binary(num(31), Op::Add, num(5))
+        );
+        assert_eq!(parse("1_000_000").unwrap(), num(1_000_000));
+        assert_eq!(parse("2.5").unwrap(), Expression::Float(2.5));
+        assert_eq!(parse("1e3").unwrap(), Expression::Float(1000.0));
+        assert_eq!(parse(".5e-1").unwrap_err().span(), Span::new(0, 1));
+        assert!(matches!(
+            parse("0x"),
+            Err(ParserError::InvalidNumber(_, Span { start: 0, end: 2 }))
+        ));
+        assert!(matches!(
+            parse("1.5x"),
+            Err(ParserError::InvalidFloat(_, Span { start: 0, end: 4 }))
+        ));
+        assert!(matches!(
+            parse("x*1e999"),
+            Err(ParserError::FloatOutOfRange(Span { start: 2, end: 7 }))
+        ));
+    }
+
+    #[test]
+    fn booleans() {
+        let compare =
+            |lhs, comparison, rhs| Expression::Compare(Box::new(lhs), comparison, Box::new(rhs));
+        let logical =
+            |lhs, logical, rhs| Expression::Logical(Box::new(lhs), logical, Box::new(rhs));
+        let not = |expr| Expression::Not(Box::new(expr));
+
+        assert_eq!(
+            parse("a+1 < b*2").unwrap(),
+            compare(
+                binary(var("a"), Op::Add, num(1)),
+                Comparison::Lt,
+                binary(var("b"), Op::Mul, num(2))
+            )
+        );
+        assert_eq!(
+            parse("a || b && !c == d").unwrap(),
+            logical(
+                var("a"),
+                Logical::Or,
+                logical(
+                    var("b"),
+                    Logical::And,
+                    compare(not(var("c")), Comparison::Eq, var("d"))
+                )
+            )
+        );
+        assert_eq!(
+            parse("a >= b != c").unwrap(),
+            compare(
+                compare(var("a"), Comparison::Ge, var("b")),
+                Comparison::Ne,
+                var("c")
+            )
+        );
+        assert_eq!(parse("!-!x").unwrap(), not(neg(not(var("x")))));
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            parse("(1+2)*3").unwrap(),
+            binary(binary(num(1), Op::Add, num(2)), Op::Mul, num(3))
+        );
+        assert_eq!(
+            parse("10-(3-2)").unwrap(),
+            binary(num(10), Op::Sub, binary(num(3), Op::Sub, num(2)))
+        );
+        assert_eq!(parse("((x))").unwrap(), var("x"));
+    }
+
+    #[test]
+    fn calls() {
+        let call = |name: &str, args| Expression::Call(name.to_string(), args);
+        assert_eq!(parse("f()").unwrap(), call("f", vec![]));
+        assert_eq!(
+            parse("max(a,-b*2)+1").unwrap(),
+            binary(
+                call(
+                    "max",
+                    vec![var("a"), binary(neg(var("b")), Op::Mul, num(2))]
+                ),
+                Op::Add,
+                num(1)
+            )
+        );
+        assert_eq!(
+            parse("f(g(x),(1))").unwrap(),
+            call("f", vec![call("g", vec![var("x")]), num(1)])
+        );
+
+        assert!(matches!(parse("f(1"), Err(ParserError::UnbalancedParen(_))));
+        assert_eq!(parse("f(1,2").unwrap_err().span(), Span::new(1, 2));
+        assert!(matches!(
+            parse("f(1,)"),
+            Err(ParserError::UnexpectedToken(Token {
+                kind: TokenKind::RightParen,
+                ..
+            }))
+        ));
+        for input in ["(1,2)", "1,2", "f(,)"] {
+            assert!(matches!(
+                parse(input),
+                Err(ParserError::UnexpectedToken(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }))
+            ));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let span = |input: &str| parse(input).unwrap_err().span();
+
+        assert!(matches!(
+            parse("(1+2"),
+            Err(ParserError::UnbalancedParen(Span { start: 0, end: 1 }))
+        ));
+        assert!(matches!(
+            parse("1+2)"),
+            Err(ParserError::UnbalancedParen(Span { start: 3, end: 4 }))
+        ));
+        assert!(matches!(parse("1+"), Err(ParserError::UnexpectedEOF(_))));
+        assert_eq!(span("1+"), Span::new(2, 2));
+        assert!(matches!(parse(""), Err(ParserError::UnexpectedEOF(_))));
+        assert!(matches!(
+            parse("()"),
+            Err(ParserError::UnexpectedToken(Token {
+                kind: TokenKind::RightParen,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            parse("1 2"),
+            Err(ParserError::UnexpectedToken(Token {
+                kind: TokenKind::Number(_),
+                ..
+            }))
+        ));
+        assert_eq!(span("1 2"), Span::new(2, 3));
+        assert!(matches!(parse("1$"), Err(ParserError::TokenizerError(_))));
+        assert!(matches!(
+            parse("(1)2"),
+            Err(ParserError::UnexpectedToken(Token {
+                kind: TokenKind::Number(_),
+                ..
+            }))
+        ));
+        assert_eq!(span("(1)23"), Span::new(3, 5));
+        assert!(matches!(
+            parse("*2"),
+            Err(ParserError::UnexpectedToken(Token {
+                kind: TokenKind::Operator(Op::Mul),
+                ..
+            }))
+        ));
+        assert!(matches!(
+            parse("1+99999999999"),
+            Err(ParserError::InvalidNumber(_, Span { start: 2, end: 13 }))
+        ));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let input = "1+foo*$";
+        assert_eq!(
+            parse(input).unwrap_err().render(input),
+            "error: Tokenizer error: Unexpected character '$' in input
+ --> 1:7
+  |
+1 | 1+foo*$
+  |       ^"
+        );
+
+        let input = "(1+2) # sum\n*3)";
+        assert_eq!(
+            parse(input).unwrap_err().render(input),
+            "error: Unbalanced parenthesis
+ --> 2:3
+  |
+2 | *3)
+  |   ^"
+        );
+
+        let input = "(1+2)*(3+456)7";
+        assert_eq!(
+            parse(input).unwrap_err().render(input),
+            "error: Unexpected token `7`
+ --> 1:14
+  |
+1 | (1+2)*(3+456)7
+  |              ^"
+        );
+
+        let input = "1\n2\n3\n4\n5\n6\n7\n8\n9\n(10+x";
+        assert_eq!(
+            render_diagnostic(input, Span::new(18, 19), "Unbalanced parenthesis"),
+            "error: Unbalanced parenthesis
+  --> 10:1
+   |
+10 | (10+x
+   | ^"
+        );
+        let input = "1 +\n2";
+        assert_eq!(Span::new(4, 5).line_col(input), (2, 1));
+        assert_eq!(
+            render_diagnostic("x*(é+", Span::new(6, 6), "Unexpected end of input"),
+            "error: Unexpected end of input
+ --> 1:6
+  |
+1 | x*(é+
+  |      ^"
+        );
+        assert_eq!(
+            render_diagnostic("foo\nbar", Span::new(1, 6), "Multi-line"),
+            "error: Multi-line
+ --> 1:2
+  |
+1 | foo
+  |  ^^"
+        );
+    }
+
+    #[test]
+    fn display() {
+        for (input, printed) in [
+            ("((1+2))*3", "(1+2)*3"),
+            ("1+(2*3)", "1+2*3"),
+            ("(10-3)-2", "10-3-2"),
+            ("10-(3-2)", "10-(3-2)"),
+            ("a/(b*c)%d", "a/(b*c)%d"),
+            ("-(x)*-(y+1)", "-x*-(y+1)"),
+            ("--(-x)", "---x"),
+            ("1-(-2)", "1--2"),
+            ("f()*-g((x),y+1)", "f()*-g(x,y+1)"),
+            ("(a<b)==(c||d)&&e", "a<b==(c||d)&&e"),
+            ("!(a&&b) || !(-x)", "!(a&&b)||!-x"),
+            ("1.50 * 2e3", "1.5*2000.0"),
+        ] {
+            let expr = parse(input).unwrap();
+            assert_eq!(expr.to_string(), printed);
+            assert_eq!(parse(printed).unwrap(), expr);
+        }
+
+        let long = vec!["x"; 100_000].join("-");
+        assert_eq!(parse(&long).unwrap().to_string(), long);
+    }
+
+    #[test]
+    fn debug() {
+        let expr = parse("-x*f(1, 2.5, g()) < !(a||b)").unwrap();
+        assert_eq!(
+            format!("{expr:?}"),
+            "Compare(Operation(Neg(Var(\"x\")), Mul, Call(\"f\", [Number(1), Float(2.5), \
+             Call(\"g\", [])])), Lt, Not(Logical(Var(\"a\"), Or, Var(\"b\"))))"
+        );
+        assert_eq!(
+            format!("{:#?}", parse("f(-x, g())").unwrap()),
+            "Call(
+    \"f\",
+    [
+        Neg(
+            Var(
+                \"x\",
+            ),
+        ),
+        Call(
+            \"g\",
+            [],
+        ),
+    ],
+)"
+        );
+    }
+
+    #[test]
+    fn long_expressions() {
+        let long = vec!["1"; 100_000].join("+");
+        let expr = parse(&long).unwrap();
+        let debug = format!("{expr:?}");
+        assert!(debug.starts_with("Operation(Operation(Operation("));
+        assert!(debug.ends_with("), Add, Number(1)), Add, Number(1))"));
+        assert_eq!(debug.matches("Number(1)").count(), 100_000);
+        assert_eq!(parse(&long).unwrap(), expr);
+        assert_ne!(
+            parse(&format!("{long}+2")).unwrap(),
+            parse(&format!("{long}+1")).unwrap()
+        );
+    }
+
+    #[test]
+    fn clone() {
+        let expr = parse("-f(x, !(y<2), 1.5) * (a || b) % 3").unwrap();
+        assert_eq!(expr.clone(), expr);
+
+        let long = vec!["x"; 100_000].join("-");
+        assert_eq!(parse(&long).unwrap().clone().to_string(), long);
+    }
+}
@@ -0,0 +1,299 @@
+use std::io::{self, BufRead, Write};
+
+use super::derivative::differentiate;
+use super::eval::{eval, Context};
+use super::optimizer::optimize;
+use super::parser::parse;
+use super::tokenizer::{tokenize, Token, TokenKind};
+
+/// An interactive session of the expression language, remembering the
+/// variables assigned with `let` and the functions defined with `fn` across
+/// lines.
+///
+/// Each line is one of:
+///
+/// - an expression, whose value is printed,
+/// - `let <name> = <expr>`, binding the value of the expression to `name`,
+/// - `fn <name>(<params>) = <expr>`, defining a function of the
+///   comma-separated parameters,
+/// - `:ast <expr>` or `:tokens <expr>`, printing the parsed expression or
+///   the tokens of `<expr>`,
+/// - `:opt <expr>`, printing the optimized expression,
+/// - `:diff <var> <expr>`, printing the derivative of `<expr>` with respect
+///   to `<var>`,
+/// - `:vars`, printing all bound variables.
+#[derive(Default)]
+pub struct Repl {
+    context: Context,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run every line of `input`, writing results and diagnostics to
+    /// `output`. If `prompt` is set, a prompt is written before each line.
+    pub fn run(
+        &mut self,
+        input: impl BufRead,
+        mut output: impl Write,
+        prompt: bool,
+    ) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(output, "> ")?;
+                output.flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                break;
+            };
+            let response = self.line(line.trim());
+            if !response.is_empty() {
+                writeln!(output, "{response}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a single line, returning what should be printed for it.
+    pub fn line(&mut self, line: &str) -> String {
+        if line.is_empty() {
+            return String::new();
+        }
+        if let Some(command) = line.strip_prefix(':') {
+            let (command, argument) = command.split_once(' ').unwrap_or((command, ""));
+            return self.command(command, argument.trim());
+        }
+        if let Some(assignment) = line.strip_prefix("let ") {
+            let Some((name, input)) = assignment.split_once('=') else {
+                return "error: Expected `let <name> = <expr>`".to_string();
+            };
+            let name = name.trim();
+            if !is_identifier(name) {
+                return format!("error: Invalid variable name '{name}'");
+            }
+            return match self.evaluate(input.trim()) {
+                Ok(value) => {
+                    self.context.variables.insert(name.to_string(), value);
+                    format!("{name} = {value}")
+                }
+                Err(e) => e,
+            };
+        }
+        if let Some(definition) = line.strip_prefix("fn ") {
+            return self.define(definition);
+        }
+        match self.evaluate(line) {
+            Ok(value) => value.to_string(),
+            Err(e) => e,
+        }
+    }
+
+    fn command(&self, command: &str, argument: &str) -> String {
+        match command {
+            "ast" => match parse(argument) {
+                Ok(expr) => format!("{expr:?}"),
+                Err(e) => e.render(argument),
+            },
+            "opt" => match parse(argument) {
+                Ok(expr) => optimize(&expr).to_string(),
+                Err(e) => e.render(argument),
+            },
+            "diff" => {
+                let (var, input) = argument.split_once(' ').unwrap_or((argument, ""));
+                let input = input.trim();
+                if !is_identifier(var) {
+                    return "error: Expected `:diff <var> <expr>`".to_string();
+                }
+                match parse(input) {
+                    Ok(expr) => match differentiate(&expr, var) {
+                        Ok(derivative) => derivative.to_string(),
+                        Err(e) => format!("error: {e}"),
+                    },
+                    Err(e) => e.render(input),
+                }
+            }
+            "tokens" => tokenize(argument)
+                .map(|tok| match tok {
+                    Ok(tok) => format!("{}..{} {:?}", tok.span.start, tok.span.end, tok.kind),
+                    Err(e) => format!("error: {e}"),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "vars" => {
+                let mut vars: Vec<_> = self.context.variables.iter().collect();
+                vars.sort();
+                vars.iter()
+                    .map(|(name, value)| format!("{name} = {value}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            _ => format!("error: Unknown command ':{command}'"),
+        }
+    }
+
+    /// Define a function from `<name>(<params>) = <expr>`.
+    fn define(&mut self, definition: &str) -> String {
+        let usage = "error: Expected `fn <name>(<params>) = <expr>`".to_string();
+        let Some((head, body)) = definition.split_once('=') else {
+            return usage;
+        };
+        let Some((name, params)) = head
+            .trim()
+            .strip_suffix(')')
+            .and_then(|head| head.split_once('('))
+        else {
+            return usage;
+        };
+        let name = name.trim();
+        let params: Vec<String> = match params.trim() {
+            "" => Vec::new(),
+            params => params
+                .split(',')
+                .map(|param| param.trim().to_string())
+                .collect(),
+        };
+        if let Some(invalid) = [name]
+            .into_iter()
+            .chain(params.iter().map(String::as_str))
+            .find(|name| !is_identifier(name))
+        {
+            return format!("error: Invalid name '{invalid}'");
+        }
+
+        let body = body.trim();
+        let expr = match parse(body) {
+            Ok(expr) => expr,
+            Err(e) => return e.render(body),
+        };
+        let signature = format!("fn {name}({})", params.join(", "));
+        match self.context.functions.define(name, params, &expr) {
+            Ok(()) => signature,
+            Err(e) => format!("error: {e}"),
+        }
+    }
+
+    /// Parse and evaluate `input`, rendering any error as a diagnostic.
+    fn evaluate(&self, input: &str) -> Result<i64, String> {
+        let expr = parse(input).map_err(|e| e.render(input))?;
+        eval(&expr, &self.context).map_err(|e| format!("error: {e}"))
+    }
+}
+
+/// Whether `name` can be used as a variable.
+fn is_identifier(name: &str) -> bool {
+    matches!(
+        tokenize(name).next(),
+        Some(Ok(Token { kind: TokenKind::Identifier(ident), .. })) if ident == name
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(script: &str) -> String {
+        let mut output = Vec::new();
+        Repl::new()
+            .run(script.as_bytes(), &mut output, false)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let script = "
+1+2*3
+let x = 10-3-2
+let y=x*x
+y%7
+:vars
+:ast -x*(y+1)
+:opt 2*x*3+0
+:diff x x*x*y+3*x
+fn sq(n) = n*n
+fn hyp2(a, b) = sq(a)+sq(b)
+fn one() = 1
+hyp2(x,max(y,3))+one()
+:tokens x*12
+";
+        assert_eq!(
+            run(script),
+            "7
+x = 5
+y = 25
+4
+x = 5
+y = 25
+Operation(Neg(Var(\"x\")), Mul, Operation(Var(\"y\"), Add, Number(1)))
+x*6
+(x+x)*y+3
+fn sq(n)
+fn hyp2(a, b)
+fn one()
+651
+0..1 Identifier(\"x\")
+1..2 Operator(Mul)
+2..4 Number(\"12\")
+"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let script = "
+let x = 1/0
+x
+(1+2
+let 2 = 3
+let x
+:ast 1+
+:tokens 1$
+:quux
+:diff 2 x
+:diff x pow(2,x)
+fn f(x) = x+y
+fn f(x, 2) = 1
+fn f x = 1
+abs(1,2)
+";
+        assert_eq!(
+            run(script),
+            "error: Division by zero
+error: Unbound variable 'x'
+error: Unbalanced parenthesis
+ --> 1:1
+  |
+1 | (1+2
+  | ^
+error: Invalid variable name '2'
+error: Expected `let <name> = <expr>`
+error: Unexpected end of input
+ --> 1:3
+  |
+1 | 1+
+  |   ^
+0..1 Number(\"1\")
+error: Unexpected character '$' in input
+error: Unknown command ':quux'
+error: Expected `:diff <var> <expr>`
+error: Can't differentiate an exponent depending on 'x'
+error: Unbound variable 'y'
+error: Invalid name '2'
+error: Expected `fn <name>(<params>) = <expr>`
+error: Function 'abs' takes 1 arguments but 2 were given
+"
+        );
+    }
+
+    #[test]
+    fn prompt() {
+        let mut output = Vec::new();
+        Repl::new()
+            .run("1\n".as_bytes(), &mut output, true)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> 1\n> ");
+    }
+}
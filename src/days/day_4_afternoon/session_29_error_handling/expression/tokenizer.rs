@@ -0,0 +1,415 @@
+use std::fmt::{self, Write as _};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use thiserror::Error;
+
+/// An arithmetic operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Rem => "%",
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// A comparison operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// A short-circuiting boolean operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Logical {
+    And,
+    Or,
+}
+
+impl fmt::Display for Logical {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Logical::And => "&&",
+            Logical::Or => "||",
+        })
+    }
+}
+
+/// A range of bytes in the input.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The 1-based line and column, in characters, of the start of the span.
+    pub fn line_col(&self, input: &str) -> (usize, usize) {
+        let before = &input[..self.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+/// Quote the line of `input` containing the start of `span`, with carets
+/// under the span, like:
+///
+/// ```text
+/// error: Unexpected character '$'
+///  --> 2:3
+///   |
+/// 2 | 1+$
+///   |   ^
+/// ```
+pub fn render_diagnostic(input: &str, span: Span, message: &str) -> String {
+    let (line, column) = span.line_col(input);
+    let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[span.start..]
+        .find('\n')
+        .map_or(input.len(), |i| span.start + i);
+    let text = &input[line_start..line_end];
+    // Spans running past the end of the line are underlined up to its end,
+    // and empty spans (the end of input) get a single caret.
+    let width = input[span.start..span.end.min(line_end)]
+        .chars()
+        .count()
+        .max(1);
+
+    let gutter = " ".repeat(line.to_string().len());
+    let mut out = String::new();
+    writeln!(out, "error: {message}").unwrap();
+    writeln!(out, "{gutter}--> {line}:{column}").unwrap();
+    writeln!(out, "{gutter} |").unwrap();
+    writeln!(out, "{line} | {text}").unwrap();
+    write!(
+        out,
+        "{gutter} | {}{}",
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+    .unwrap();
+    out
+}
+
+/// The kinds of token in the expression language.
+#[derive(Debug, PartialEq)]
+pub enum TokenKind {
+    /// An integer literal: decimal, or hexadecimal or binary with a `0x` or
+    /// `0b` prefix, optionally with `_` separators.
+    Number(String),
+    /// A floating-point literal, with a fraction, an exponent or both.
+    Float(String),
+    Identifier(String),
+    Operator(Op),
+    Comparison(Comparison),
+    Logical(Logical),
+    Not,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Number(num) | TokenKind::Float(num) => f.write_str(num),
+            TokenKind::Identifier(ident) => f.write_str(ident),
+            TokenKind::Operator(op) => write!(f, "{op}"),
+            TokenKind::Comparison(comparison) => write!(f, "{comparison}"),
+            TokenKind::Logical(logical) => write!(f, "{logical}"),
+            TokenKind::Not => f.write_str("!"),
+            TokenKind::LeftParen => f.write_str("("),
+            TokenKind::RightParen => f.write_str(")"),
+            TokenKind::Comma => f.write_str(","),
+        }
+    }
+}
+
+/// A token in the expression language, with where it was found in the input.
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+pub fn tokenize(input: &str) -> Tokenizer<'_> {
+    Tokenizer {
+        input,
+        chars: input.char_indices().peekable(),
+    }
+}
+
+#[derive(Debug, Error, Clone)]
+pub enum TokenizerError {
+    #[error("Unexpected character {0:?} in input")]
+    UnexpectedCharacter(char, Span),
+}
+
+impl TokenizerError {
+    /// Where in the input the error occurred.
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizerError::UnexpectedCharacter(_, span) => *span,
+        }
+    }
+}
+
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    /// The byte offset of the next character.
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |&(i, _)| i)
+    }
+
+    /// Consume the next character if it satisfies `pred`.
+    fn eat(&mut self, pred: impl Fn(char) -> bool) -> bool {
+        self.chars.next_if(|&(_, c)| pred(c)).is_some()
+    }
+
+    /// Consume the next character if it is `expected`.
+    fn eat_char(&mut self, expected: char) -> bool {
+        self.eat(|c| c == expected)
+    }
+
+    /// Whether the character after the next one satisfies `pred`.
+    fn second_is(&self, pred: impl Fn(char) -> bool) -> bool {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().is_some_and(|(_, c)| pred(c))
+    }
+
+    /// Skip whitespace and `#` comments, which run to the end of the line.
+    fn skip_trivia(&mut self) {
+        loop {
+            if self.eat(char::is_whitespace) {
+                continue;
+            }
+            if !self.eat(|c| c == '#') {
+                break;
+            }
+            while self.eat(|c| c != '\n') {}
+        }
+    }
+
+    /// Lex the rest of a number literal starting with `first` at `start`.
+    fn number(&mut self, start: usize, first: char) -> TokenKind {
+        let alphanumeric = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let digit = |c: char| c.is_ascii_digit() || c == '_';
+
+        let radix_prefix = first == '0' && self.eat(|c| matches!(c, 'x' | 'X' | 'b' | 'B'));
+        let mut float = false;
+        if !radix_prefix {
+            while self.eat(digit) {}
+            if self.chars.peek().is_some_and(|&(_, c)| c == '.')
+                && self.second_is(|c| c.is_ascii_digit())
+            {
+                self.eat_char('.');
+                while self.eat(digit) {}
+                float = true;
+            }
+            if self
+                .chars
+                .peek()
+                .is_some_and(|&(_, c)| matches!(c, 'e' | 'E'))
+            {
+                let mut lookahead = self.chars.clone();
+                lookahead.next();
+                lookahead.next_if(|&(_, c)| matches!(c, '+' | '-'));
+                if lookahead.peek().is_some_and(|&(_, c)| c.is_ascii_digit()) {
+                    self.chars = lookahead;
+                    while self.eat(digit) {}
+                    float = true;
+                }
+            }
+        }
+        // Anything alphanumeric running on is part of the literal, so that it
+        // is reported as an invalid number rather than split up.
+        while self.eat(alphanumeric) {}
+        let text = self.input[start..self.offset()].to_string();
+        if float {
+            TokenKind::Float(text)
+        } else {
+            TokenKind::Number(text)
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token, TokenizerError>;
+
+    fn next(&mut self) -> Option<Result<Token, TokenizerError>> {
+        self.skip_trivia();
+        let (start, c) = self.chars.next()?;
+        let kind = match c {
+            '0'..='9' => self.number(start, c),
+            'a'..='z' | 'A'..='Z' | '_' => {
+                while self.eat(|c| c.is_ascii_alphanumeric() || c == '_') {}
+                TokenKind::Identifier(self.input[start..self.offset()].to_string())
+            }
+            '+' => TokenKind::Operator(Op::Add),
+            '-' => TokenKind::Operator(Op::Sub),
+            '*' => TokenKind::Operator(Op::Mul),
+            '/' => TokenKind::Operator(Op::Div),
+            '%' => TokenKind::Operator(Op::Rem),
+            '=' if self.eat_char('=') => TokenKind::Comparison(Comparison::Eq),
+            '!' if self.eat_char('=') => TokenKind::Comparison(Comparison::Ne),
+            '!' => TokenKind::Not,
+            '<' if self.eat_char('=') => TokenKind::Comparison(Comparison::Le),
+            '<' => TokenKind::Comparison(Comparison::Lt),
+            '>' if self.eat_char('=') => TokenKind::Comparison(Comparison::Ge),
+            '>' => TokenKind::Comparison(Comparison::Gt),
+            '&' if self.eat_char('&') => TokenKind::Logical(Logical::And),
+            '|' if self.eat_char('|') => TokenKind::Logical(Logical::Or),
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            ',' => TokenKind::Comma,
+            _ => {
+                let span = Span::new(start, start + c.len_utf8());
+                return Some(Err(TokenizerError::UnexpectedCharacter(c, span)));
+            }
+        };
+        let span = Span::new(start, self.offset());
+        Some(Ok(Token { kind, span }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_spans() {
+        let tokens: Vec<_> = tokenize("foo_1*(23)")
+            .map(|tok| tok.unwrap().span)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Span::new(0, 5),
+                Span::new(5, 6),
+                Span::new(6, 7),
+                Span::new(7, 9),
+                Span::new(9, 10),
+            ]
+        );
+        // Spans are in bytes, not characters.
+        let error = tokenize("é").next().unwrap().unwrap_err();
+        assert_eq!(error.span(), Span::new(0, 2));
+    }
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        tokenize(input).map(|tok| tok.unwrap().kind).collect()
+    }
+
+    #[test]
+    fn trivia() {
+        assert_eq!(
+            kinds(" 1 +\t2 # the rest is ignored: $\n* x # to the end"),
+            vec![
+                TokenKind::Number("1".to_string()),
+                TokenKind::Operator(Op::Add),
+                TokenKind::Number("2".to_string()),
+                TokenKind::Operator(Op::Mul),
+                TokenKind::Identifier("x".to_string()),
+            ]
+        );
+        assert_eq!(kinds("  # only a comment"), vec![]);
+    }
+
+    #[test]
+    fn numbers() {
+        let number = |text: &str| TokenKind::Number(text.to_string());
+        let float = |text: &str| TokenKind::Float(text.to_string());
+        assert_eq!(
+            kinds("0x1f 0B10 1_000 1.5 2e10 3.0E-2 4e+1"),
+            vec![
+                number("0x1f"),
+                number("0B10"),
+                number("1_000"),
+                float("1.5"),
+                float("2e10"),
+                float("3.0E-2"),
+                float("4e+1"),
+            ]
+        );
+        // An 'e' not followed by digits isn't an exponent, and is left to be
+        // reported as part of an invalid number.
+        assert_eq!(
+            kinds("2e+x"),
+            vec![
+                number("2e"),
+                TokenKind::Operator(Op::Add),
+                TokenKind::Identifier("x".to_string()),
+            ]
+        );
+        // A '.' must be followed by a digit.
+        let error = tokenize("1.x").nth(1).unwrap().unwrap_err();
+        assert_eq!(error.span(), Span::new(1, 2));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            kinds("a==b!=!c<=<>=>&&||"),
+            vec![
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Comparison(Comparison::Eq),
+                TokenKind::Identifier("b".to_string()),
+                TokenKind::Comparison(Comparison::Ne),
+                TokenKind::Not,
+                TokenKind::Identifier("c".to_string()),
+                TokenKind::Comparison(Comparison::Le),
+                TokenKind::Comparison(Comparison::Lt),
+                TokenKind::Comparison(Comparison::Ge),
+                TokenKind::Comparison(Comparison::Gt),
+                TokenKind::Logical(Logical::And),
+                TokenKind::Logical(Logical::Or),
+            ]
+        );
+        for input in ["=", "&", "|"] {
+            assert!(tokenize(input).next().unwrap().is_err(), "{input}");
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use super::eval::{Environment, EvalError};
+use super::functions::builtin;
+use super::parser::Expression;
+use super::tokenizer::{Comparison, Logical, Op};
+use std::collections::HashMap;
+
+/// An instruction of the stack machine. Operators pop their operands off the
+/// stack and push their result. Comparisons and boolean operators push 1 for
+/// true and 0 for false.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instruction {
+    /// Push a constant.
+    Push(i64),
+    /// A floating-point constant, which the machine can't represent and fails
+    /// on.
+    Float(f64),
+    /// Push the value of the variable with the given index in
+    /// `Program::names`.
+    Load(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Not,
+    /// Replace the top of the stack by 1 if it is non-zero, and 0 otherwise.
+    Bool,
+    /// Jump to the given instruction if the top of the stack is zero, leaving
+    /// it there. Otherwise pop it.
+    JumpIfFalse(usize),
+    /// Jump to the given instruction if the top of the stack is non-zero,
+    /// leaving it there. Otherwise pop it.
+    JumpIfTrue(usize),
+    /// Call the function with the given index in `Program::names`, with the
+    /// given number of arguments.
+    Call(usize, usize),
+}
+
+/// An expression compiled to bytecode for the stack machine.
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+    /// The names of the variables and functions used by the program.
+    pub names: Vec<String>,
+}
+
+/// Compile `expr` to a program computing its value.
+pub fn compile(expr: &Expression) -> Program {
+    enum Step<'a> {
+        Visit(&'a Expression),
+        Emit(Instruction),
+        /// Emit a jump over the right-hand side of a boolean operator, to be
+        /// patched by the matching `Land`.
+        Jump(Logical),
+        /// Emit the `Bool` that the latest unpatched jump lands on.
+        Land,
+    }
+
+    let mut program = Program {
+        code: Vec::new(),
+        names: Vec::new(),
+    };
+    // The index of each name in `program.names`, so that looking one up
+    // doesn't take time proportional to the number of names.
+    let mut indices = HashMap::new();
+    // Walk the tree in post-order with an explicit stack, so that deeply
+    // nested expressions can be compiled.
+    let mut steps = vec![Step::Visit(expr)];
+    let mut jumps = Vec::new();
+    while let Some(step) = steps.pop() {
+        let expr = match step {
+            Step::Visit(expr) => expr,
+            Step::Emit(instruction) => {
+                program.code.push(instruction);
+                continue;
+            }
+            Step::Jump(logical) => {
+                jumps.push(program.code.len());
+                program.code.push(match logical {
+                    Logical::And => Instruction::JumpIfFalse(0),
+                    Logical::Or => Instruction::JumpIfTrue(0),
+                });
+                continue;
+            }
+            Step::Land => {
+                let target = program.code.len();
+                match &mut program.code[jumps.pop().unwrap()] {
+                    Instruction::JumpIfFalse(to) | Instruction::JumpIfTrue(to) => *to = target,
+                    _ => unreachable!(),
+                }
+                program.code.push(Instruction::Bool);
+                continue;
+            }
+        };
+        match expr {
+            Expression::Number(n) => program.code.push(Instruction::Push(i64::from(*n))),
+            Expression::Float(x) => program.code.push(Instruction::Float(*x)),
+            Expression::Var(name) => {
+                let index = program.name_index(&mut indices, name);
+                program.code.push(Instruction::Load(index));
+            }
+            Expression::Call(name, args) => {
+                let index = program.name_index(&mut indices, name);
+                steps.push(Step::Emit(Instruction::Call(index, args.len())));
+                steps.extend(args.iter().rev().map(Step::Visit));
+            }
+            Expression::Neg(expr) => {
+                steps.push(Step::Emit(Instruction::Neg));
+                steps.push(Step::Visit(expr));
+            }
+            Expression::Not(expr) => {
+                steps.push(Step::Emit(Instruction::Not));
+                steps.push(Step::Visit(expr));
+            }
+            Expression::Operation(lhs, op, rhs) => {
+                let instruction = match op {
+                    Op::Add => Instruction::Add,
+                    Op::Sub => Instruction::Sub,
+                    Op::Mul => Instruction::Mul,
+                    Op::Div => Instruction::Div,
+                    Op::Rem => Instruction::Rem,
+                };
+                steps.push(Step::Emit(instruction));
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Visit(lhs));
+            }
+            Expression::Compare(lhs, comparison, rhs) => {
+                let instruction = match comparison {
+                    Comparison::Eq => Instruction::Eq,
+                    Comparison::Ne => Instruction::Ne,
+                    Comparison::Lt => Instruction::Lt,
+                    Comparison::Le => Instruction::Le,
+                    Comparison::Gt => Instruction::Gt,
+                    Comparison::Ge => Instruction::Ge,
+                };
+                steps.push(Step::Emit(instruction));
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Visit(lhs));
+            }
+            // The right-hand side is only evaluated if the left-hand side
+            // doesn't decide the result, and either value is normalized to 0
+            // or 1 by the `Bool` both paths end on.
+            Expression::Logical(lhs, logical, rhs) => {
+                steps.push(Step::Land);
+                steps.push(Step::Visit(rhs));
+                steps.push(Step::Jump(*logical));
+                steps.push(Step::Visit(lhs));
+            }
+        }
+    }
+    program
+}
+
+impl Program {
+    /// The index of `name` in `names`, adding it if needed. `indices` maps
+    /// every name in `names` to its index.
+    fn name_index(&mut self, indices: &mut HashMap<String, usize>, name: &str) -> usize {
+        if let Some(&index) = indices.get(name) {
+            return index;
+        }
+        self.names.push(name.to_string());
+        indices.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    /// Run the program with 64-bit integer arithmetic, looking up variables
+    /// and functions in `env`.
+    pub fn run(&self, env: &impl Environment) -> Result<i64, EvalError> {
+        self.execute(env, 0)
+    }
+
+    /// Run the program inside `depth` calls to user-defined functions.
+    pub(super) fn execute(&self, env: &dyn Environment, depth: usize) -> Result<i64, EvalError> {
+        let mut stack: Vec<i64> = Vec::new();
+        let mut pc = 0;
+        while let Some(&instruction) = self.code.get(pc) {
+            pc += 1;
+            let value = match instruction {
+                Instruction::Push(value) => value,
+                Instruction::Float(value) => return Err(EvalError::UnsupportedFloat(value)),
+                Instruction::Load(index) => {
+                    let name = &self.names[index];
+                    env.get(name)
+                        .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?
+                }
+                Instruction::Call(index, argc) => {
+                    let name = &self.names[index];
+                    let args = stack.split_off(stack.len() - argc);
+                    match env.function(name) {
+                        Some(function) => function.call(name, &args, env, depth)?,
+                        None => builtin(name)
+                            .ok_or_else(|| EvalError::UnknownFunction(name.clone()))?
+                            .call(name, &args, env, depth)?,
+                    }
+                }
+                Instruction::Neg => {
+                    let value = stack.pop().expect("stack underflow");
+                    value.checked_neg().ok_or(EvalError::Overflow)?
+                }
+                Instruction::Not => i64::from(stack.pop().expect("stack underflow") == 0),
+                Instruction::Bool => i64::from(stack.pop().expect("stack underflow") != 0),
+                Instruction::JumpIfFalse(target) | Instruction::JumpIfTrue(target) => {
+                    let value = *stack.last().expect("stack underflow");
+                    if (value != 0) == matches!(instruction, Instruction::JumpIfTrue(_)) {
+                        pc = target;
+                    } else {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                binary => {
+                    let rhs = stack.pop().expect("stack underflow");
+                    let lhs = stack.pop().expect("stack underflow");
+                    if matches!(binary, Instruction::Div | Instruction::Rem) && rhs == 0 {
+                        return Err(EvalError::DivisionByZero);
+                    }
+                    let result = match binary {
+                        Instruction::Add => lhs.checked_add(rhs),
+                        Instruction::Sub => lhs.checked_sub(rhs),
+                        Instruction::Mul => lhs.checked_mul(rhs),
+                        Instruction::Div => lhs.checked_div(rhs),
+                        Instruction::Rem => lhs.checked_rem(rhs),
+                        Instruction::Eq => Some(i64::from(lhs == rhs)),
+                        Instruction::Ne => Some(i64::from(lhs != rhs)),
+                        Instruction::Lt => Some(i64::from(lhs < rhs)),
+                        Instruction::Le => Some(i64::from(lhs <= rhs)),
+                        Instruction::Gt => Some(i64::from(lhs > rhs)),
+                        Instruction::Ge => Some(i64::from(lhs >= rhs)),
+                        _ => unreachable!(),
+                    };
+                    result.ok_or(EvalError::Overflow)?
+                }
+            };
+            stack.push(value);
+        }
+        Ok(stack.pop().expect("stack underflow"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parser::parse;
+    use super::*;
+
+    #[test]
+    fn bytecode() {
+        let program = compile(&parse("-(x+2)*y%x").unwrap());
+        assert_eq!(
+            program.code,
+            vec![
+                Instruction::Load(0),
+                Instruction::Push(2),
+                Instruction::Add,
+                Instruction::Neg,
+                Instruction::Load(1),
+                Instruction::Mul,
+                Instruction::Load(0),
+                Instruction::Rem,
+            ]
+        );
+        assert_eq!(program.names, vec!["x", "y"]);
+
+        let env = HashMap::from([("x".to_string(), 4), ("y".to_string(), 3)]);
+        assert_eq!(program.run(&env), Ok(-2));
+    }
+
+    #[test]
+    fn short_circuit() {
+        let program = compile(&parse("x<1||!y&&z").unwrap());
+        assert_eq!(
+            program.code,
+            vec![
+                Instruction::Load(0),
+                Instruction::Push(1),
+                Instruction::Lt,
+                Instruction::JumpIfTrue(9),
+                Instruction::Load(1),
+                Instruction::Not,
+                Instruction::JumpIfFalse(8),
+                Instruction::Load(2),
+                Instruction::Bool,
+                Instruction::Bool,
+            ]
+        );
+
+        // `z` is unbound, so it must not be evaluated.
+        let env = HashMap::from([("x".to_string(), 0), ("y".to_string(), 5)]);
+        assert_eq!(program.run(&env), Ok(1));
+        let env = HashMap::from([("x".to_string(), 3), ("y".to_string(), 5)]);
+        assert_eq!(program.run(&env), Ok(0));
+    }
+
+    #[test]
+    fn long_expressions() {
+        let terms = 100_000;
+        let env = HashMap::new();
+
+        let sum = vec!["1"; terms].join("+");
+        assert_eq!(compile(&parse(&sum).unwrap()).run(&env), Ok(terms as i64));
+
+        let nested = format!("{}1{}", "(1-".repeat(terms), ")".repeat(terms));
+        assert_eq!(compile(&parse(&nested).unwrap()).run(&env), Ok(1));
+
+        let negated = format!("{}7", "-".repeat(terms + 1));
+        assert_eq!(compile(&parse(&negated).unwrap()).run(&env), Ok(-7));
+
+        let conjunction = vec!["1"; terms].join("&&");
+        assert_eq!(compile(&parse(&conjunction).unwrap()).run(&env), Ok(1));
+
+        let variables = (0..terms).map(|i| format!("v{i}")).collect::<Vec<_>>();
+        let program = compile(&parse(&variables.join("+")).unwrap());
+        assert_eq!(program.names, variables);
+        let env = variables
+            .into_iter()
+            .map(|v| (v, 1))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(program.run(&env), Ok(terms as i64));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod expression;
 mod session_29_1_panics;
 mod session_29_2_try_operator;
 mod session_29_3_try_conversions;
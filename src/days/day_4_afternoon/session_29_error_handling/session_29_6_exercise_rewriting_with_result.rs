@@ -1,5 +1,5 @@
-use std::iter::Peekable;
-use std::str::Chars;
+use std::collections::HashMap;
+
 /// # 29.6 Exercise: Rewriting with Result
 ///
 /// The following implements a very simple parser for an expression language. However,
@@ -113,120 +113,15 @@ use std::str::Chars;
 ///
 /// ```
 ///
-use thiserror::Error;
-
-/// An arithmetic operator.
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum Op {
-    Add,
-    Sub,
-}
-
-/// A token in the expression language.
-#[derive(Debug, PartialEq)]
-enum Token {
-    Number(String),
-    Identifier(String),
-    Operator(Op),
-}
-
-/// An expression in the expression language.
-#[derive(Debug, PartialEq)]
-enum Expression {
-    /// A reference to a variable.
-    Var(String),
-    /// A literal number.
-    Number(u32),
-    /// A binary operation.
-    Operation(Box<Expression>, Op, Box<Expression>),
-}
-
-fn tokenize(input: &str) -> Tokenizer {
-    return Tokenizer(input.chars().peekable());
-}
-
-#[derive(Debug, Error)]
-enum TokenizerError {
-    #[error("Unexpected character '{0}' in input")]
-    UnexpectedCharacter(char),
-}
-
-struct Tokenizer<'a>(Peekable<Chars<'a>>);
-
-impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Result<Token, TokenizerError>;
-
-    fn next(&mut self) -> Option<Result<Token, TokenizerError>> {
-        let Some(c) = self.0.next() else {
-            return None;
-        };
-        match c {
-            '0'..='9' => {
-                let mut num = String::from(c);
-                while let Some(c @ '0'..='9') = self.0.peek() {
-                    num.push(*c);
-                    self.0.next();
-                }
-                Some(Ok(Token::Number(num)))
-            }
-            'a'..='z' => {
-                let mut ident = String::from(c);
-                while let Some(c @ 'a'..='z' | c @ '_' | c @ '0'..='9') = self.0.peek() {
-                    ident.push(*c);
-                    self.0.next();
-                }
-                Some(Ok(Token::Identifier(ident)))
-            }
-            '+' => Some(Ok(Token::Operator(Op::Add))),
-            '-' => Some(Ok(Token::Operator(Op::Sub))),
-            _ => Some(Err(TokenizerError::UnexpectedCharacter(c))),
-        }
-    }
-}
-
-#[derive(Debug, Error)]
-enum ParserError {
-    #[error("Tokenizer error: {0}")]
-    TokenizerError(#[from] TokenizerError),
-    #[error("Unexpected end of input")]
-    UnexpectedEOF,
-    #[error("Unexpected token {0:?}")]
-    UnexpectedToken(Token),
-    #[error("Invalid number")]
-    InvalidNumber(#[from] std::num::ParseIntError),
-}
-
-fn parse(input: &str) -> Result<Expression, ParserError> {
-    let mut tokens = tokenize(input);
-
-    fn parse_expr(tokens: &mut Tokenizer) -> Result<Expression, ParserError> {
-        let Some(tok) = tokens.next().transpose()? else {
-            return Err(ParserError::UnexpectedEOF);
-        };
-        let expr = match tok {
-            Token::Number(num) => {
-                let v = num.parse()?;
-                Expression::Number(v)
-            }
-            Token::Identifier(ident) => Expression::Var(ident),
-            Token::Operator(_) => return Err(ParserError::UnexpectedToken(tok)),
-        };
-        // Look ahead to parse a binary operation if present.
-        Ok(match tokens.next() {
-            None => expr,
-            Some(Ok(Token::Operator(op))) => {
-                Expression::Operation(Box::new(expr), op, Box::new(parse_expr(tokens)?))
-            }
-            Some(Err(e)) => return Err(e.into()),
-            Some(Ok(tok)) => return Err(ParserError::UnexpectedToken(tok)),
-        })
-    }
-
-    parse_expr(&mut tokens)
-}
+use super::expression::eval::eval;
+use super::expression::parser::parse;
 
 pub fn main() -> anyhow::Result<()> {
-    let expr = parse("10+foo+20-30")?;
-    println!("{expr:?}");
+    let env = HashMap::from([("foo".to_string(), 7), ("x".to_string(), 3)]);
+    for input in ["10+foo+20-30", "-(1+2)*x%4-10/5", "pow(2,10)-max(foo,x)"] {
+        let expr = parse(input).map_err(|e| anyhow::anyhow!(e.render(input)))?;
+        println!("{expr:?}");
+        println!("{input} = {}", eval(&expr, &env)?);
+    }
     Ok(())
 }